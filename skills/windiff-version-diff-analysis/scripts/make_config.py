@@ -11,7 +11,7 @@ compare, so the CLI run downloads only what the diff needs.
 Usage:
     make_config.py --os "VERSION:UPDATE:ARCH" --os "VERSION:UPDATE:ARCH" \
                    --binary ntoskrnl.exe [--binary ntdll.dll ...] \
-                   [--info EXPORTS DEBUG_SYMBOLS MODULES TYPES SYSCALLS]
+                   [--info EXPORTS DEBUG_SYMBOLS MODULES TYPES SYSCALLS CALLBACKS RPC TYPELIB MESSAGE_TABLE APISET MANAGED_TYPES ETW_TRACELOGGING ETW_MANIFEST STRINGS FEATURES WNF POOL_TAGS FUNCTION_HASHES CALL_GRAPH VTABLES RTTI TLS_EXCEPTIONS ...]
 
 Example:
     make_config.py --os "21H2:BASE:amd64" --os "11-24H2:KB5074105:amd64" \
@@ -21,7 +21,9 @@ import argparse
 import json
 import sys
 
-ALL_INFO = ["EXPORTS", "DEBUG_SYMBOLS", "MODULES", "TYPES", "SYSCALLS"]
+DEFAULT_INFO = ["EXPORTS", "DEBUG_SYMBOLS", "MODULES", "TYPES", "SYSCALLS"]
+# Opt-in kinds, only extracted when explicitly requested with --info
+ALL_INFO = DEFAULT_INFO + ["CALLBACKS", "RPC", "TYPELIB", "MESSAGE_TABLE", "APISET", "MANAGED_TYPES", "ETW_TRACELOGGING", "ETW_MANIFEST", "STRINGS", "FEATURES", "WNF", "POOL_TAGS", "FUNCTION_HASHES", "CALL_GRAPH", "VTABLES", "RTTI", "TLS_EXCEPTIONS"]
 VALID_ARCH = {"i386", "wow64", "amd64", "arm", "arm64"}
 
 
@@ -43,8 +45,8 @@ def main():
         "--info",
         nargs="+",
         choices=ALL_INFO,
-        default=ALL_INFO,
-        help="Which data kinds to extract per binary (default: all)",
+        default=DEFAULT_INFO,
+        help="Which data kinds to extract per binary (default: %(default)s)",
     )
     args = parser.parse_args()
 
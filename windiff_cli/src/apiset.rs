@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::{Result, WinDiffError},
+    pe_utils::{read_bytes, read_u32, utf16_to_string},
+};
+
+const API_SET_SECTION_NAME: &str = ".apiset";
+/// Flag set on extension contracts in version 4 schemas
+const API_SET_SCHEMA_ENTRY_FLAGS_EXTENSION_V4: u32 = 0x2;
+
+/// API set contract, as defined by the API set schema
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct ApiSetContract {
+    /// Host DLLs the contract resolves to by default
+    pub hosts: Vec<String>,
+    /// Host DLLs the contract resolves to for specific importers (importing
+    /// DLL -> host DLL)
+    pub exceptions: BTreeMap<String, String>,
+}
+
+/// Extract the API set schema from the `.apiset` section of a given PE (i.e.,
+/// apisetschema.dll), as (contract name, contract) pairs. Returns an empty list
+/// if the PE doesn't contain an API set schema.
+pub fn extract_api_set(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(String, ApiSetContract)>> {
+    let section = match pe
+        .sections
+        .iter()
+        .find(|section| section.name().unwrap_or_default() == API_SET_SECTION_NAME)
+    {
+        Some(section) => section,
+        None => return Ok(vec![]),
+    };
+    log::trace!("Extracting API set schema");
+
+    let schema = read_bytes(
+        pe_data,
+        section.pointer_to_raw_data as usize,
+        section.size_of_raw_data as usize,
+    )?;
+    parse_api_set_schema(schema)
+}
+
+/// Parse an API set schema (versions 2, 4 and 6 are supported)
+pub fn parse_api_set_schema(schema: &[u8]) -> Result<Vec<(String, ApiSetContract)>> {
+    let version = read_u32(schema, 0)?;
+    match version {
+        2 => parse_api_set_schema_v2(schema),
+        4 => parse_api_set_schema_v4(schema),
+        6 => parse_api_set_schema_v6(schema),
+        _ => Err(WinDiffError::UnsupportedApiSetSchemaVersion(version)),
+    }
+}
+
+/// Parse a version 2 schema (Windows 7 and 8)
+fn parse_api_set_schema_v2(schema: &[u8]) -> Result<Vec<(String, ApiSetContract)>> {
+    // API_SET_NAMESPACE_ARRAY_V2
+    let entry_count = read_u32(schema, 4)? as usize;
+
+    let mut result = Vec::with_capacity(entry_count);
+    for entry_index in 0..entry_count {
+        // API_SET_NAMESPACE_ENTRY_V2
+        let entry_offset = 8 + entry_index * 12;
+        // Contract names are stored without their "api-" prefix
+        let contract_name = format!(
+            "api-{}",
+            read_utf16_string(schema, entry_offset)?.to_lowercase()
+        );
+        // API_SET_VALUE_ARRAY_V2
+        let value_array_offset = read_u32(schema, entry_offset + 8)? as usize;
+        let value_count = read_u32(schema, value_array_offset)? as usize;
+        let values = (0..value_count)
+            .map(|value_index| {
+                // API_SET_VALUE_ENTRY_V2
+                read_value_entry(schema, value_array_offset + 4 + value_index * 16)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        result.push((contract_name, build_contract(values)));
+    }
+
+    Ok(result)
+}
+
+/// Parse a version 4 schema (Windows 8.1)
+fn parse_api_set_schema_v4(schema: &[u8]) -> Result<Vec<(String, ApiSetContract)>> {
+    // API_SET_NAMESPACE_ARRAY_V4
+    let entry_count = read_u32(schema, 12)? as usize;
+
+    let mut result = Vec::with_capacity(entry_count);
+    for entry_index in 0..entry_count {
+        // API_SET_NAMESPACE_ENTRY_V4
+        let entry_offset = 16 + entry_index * 24;
+        let entry_flags = read_u32(schema, entry_offset)?;
+        // Contract names are stored without their "api-"/"ext-" prefix
+        let prefix = if entry_flags & API_SET_SCHEMA_ENTRY_FLAGS_EXTENSION_V4 != 0 {
+            "ext-"
+        } else {
+            "api-"
+        };
+        let contract_name = format!(
+            "{}{}",
+            prefix,
+            read_utf16_string(schema, entry_offset + 4)?.to_lowercase()
+        );
+        // API_SET_VALUE_ARRAY_V4
+        let value_array_offset = read_u32(schema, entry_offset + 20)? as usize;
+        let value_count = read_u32(schema, value_array_offset + 4)? as usize;
+        let values = (0..value_count)
+            .map(|value_index| {
+                // API_SET_VALUE_ENTRY_V4
+                read_value_entry(schema, value_array_offset + 8 + value_index * 20 + 4)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        result.push((contract_name, build_contract(values)));
+    }
+
+    Ok(result)
+}
+
+/// Parse a version 6 schema (Windows 10 and later)
+fn parse_api_set_schema_v6(schema: &[u8]) -> Result<Vec<(String, ApiSetContract)>> {
+    // API_SET_NAMESPACE
+    let entry_count = read_u32(schema, 12)? as usize;
+    let entries_offset = read_u32(schema, 16)? as usize;
+
+    let mut result = Vec::with_capacity(entry_count);
+    for entry_index in 0..entry_count {
+        // API_SET_NAMESPACE_ENTRY
+        let entry_offset = entries_offset + entry_index * 24;
+        let contract_name = read_utf16_string(schema, entry_offset + 4)?.to_lowercase();
+        let values_offset = read_u32(schema, entry_offset + 16)? as usize;
+        let value_count = read_u32(schema, entry_offset + 20)? as usize;
+        let values = (0..value_count)
+            .map(|value_index| {
+                // API_SET_VALUE_ENTRY
+                read_value_entry(schema, values_offset + value_index * 20 + 4)
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        result.push((contract_name, build_contract(values)));
+    }
+
+    Ok(result)
+}
+
+/// Read a value entry's (importer name, host name) pair, starting at its
+/// `NameOffset` field
+fn read_value_entry(schema: &[u8], offset: usize) -> Result<(String, String)> {
+    Ok((
+        read_utf16_string(schema, offset)?.to_lowercase(),
+        read_utf16_string(schema, offset + 8)?.to_lowercase(),
+    ))
+}
+
+/// Build a contract from its (importer name, host name) values. Values without
+/// importer name are default hosts.
+fn build_contract(values: Vec<(String, String)>) -> ApiSetContract {
+    let mut contract = ApiSetContract::default();
+    for (importer_name, host_name) in values {
+        if importer_name.is_empty() {
+            if !host_name.is_empty() {
+                contract.hosts.push(host_name);
+            }
+        } else {
+            contract.exceptions.insert(importer_name, host_name);
+        }
+    }
+
+    contract
+}
+
+/// Read a UTF-16 string referenced by an (offset, length in bytes) pair stored
+/// at the given offset
+fn read_utf16_string(schema: &[u8], offset: usize) -> Result<String> {
+    let string_offset = read_u32(schema, offset)? as usize;
+    let string_length = read_u32(schema, offset + 4)? as usize;
+    if string_length == 0 {
+        return Ok(String::default());
+    }
+
+    Ok(utf16_to_string(read_bytes(
+        schema,
+        string_offset,
+        string_length,
+    )?))
+}
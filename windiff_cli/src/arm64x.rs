@@ -0,0 +1,276 @@
+use goblin::pe;
+
+use crate::{
+    error::{Result, WinDiffError},
+    pe_utils::{read_bytes, read_c_string, read_u16, read_u32, read_u64, rva_to_offset},
+};
+
+// https://learn.microsoft.com/en-us/windows/arm/arm64x-pe
+const IMAGE_DYNAMIC_RELOCATION_ARM64X: u64 = 6;
+const IMAGE_DVRT_ARM64X_FIXUP_TYPE_ZEROFILL: u16 = 0;
+const IMAGE_DVRT_ARM64X_FIXUP_TYPE_VALUE: u16 = 1;
+const IMAGE_DVRT_ARM64X_FIXUP_TYPE_DELTA: u16 = 2;
+const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+
+/// Kind of code contained in a range of a hybrid (ARM64X or ARM64EC) image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HybridCodeKind {
+    Arm64,
+    Arm64EC,
+    Amd64,
+}
+
+/// Subset of the `IMAGE_ARM64EC_METADATA` structure pointed to by the load
+/// config directory of hybrid images
+pub struct HybridMetadata {
+    /// Code ranges as (start RVA, length, kind of code)
+    code_map: Vec<(u32, u32, HybridCodeKind)>,
+}
+
+impl HybridMetadata {
+    /// Parse the hybrid metadata of a PE, returns `None` for non-hybrid PEs.
+    pub fn parse(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Option<Self>> {
+        let chpe_metadata_va = match pe
+            .load_config_data
+            .as_ref()
+            .and_then(|load_config| load_config.directory.chpe_metadata_pointer)
+        {
+            Some(va) if va != 0 => va,
+            _ => return Ok(None),
+        };
+        let chpe_metadata_rva = chpe_metadata_va
+            .checked_sub(pe.image_base)
+            .ok_or(WinDiffError::HybridMetadataParsingError)?;
+        let chpe_metadata_offset = rva_to_offset(chpe_metadata_rva as usize, pe)?;
+
+        // IMAGE_ARM64EC_METADATA.CodeMap and IMAGE_ARM64EC_METADATA.CodeMapCount
+        let code_map_rva = read_u32(pe_data, chpe_metadata_offset + 4)?;
+        let code_map_count = read_u32(pe_data, chpe_metadata_offset + 8)?;
+        let code_map_offset = rva_to_offset(code_map_rva as usize, pe)?;
+
+        // Walk through the IMAGE_CHPE_RANGE_ENTRY array
+        let mut code_map = Vec::with_capacity(code_map_count as usize);
+        for i in 0..code_map_count as usize {
+            let entry_offset = code_map_offset + i * 2 * std::mem::size_of::<u32>();
+            let start_offset = read_u32(pe_data, entry_offset)?;
+            let length = read_u32(pe_data, entry_offset + std::mem::size_of::<u32>())?;
+            // The type of code is stored in the 2 lowest bits of the start offset
+            let kind = match start_offset & 0x3 {
+                0 => HybridCodeKind::Arm64,
+                1 => HybridCodeKind::Arm64EC,
+                2 => HybridCodeKind::Amd64,
+                _ => return Err(WinDiffError::HybridMetadataParsingError),
+            };
+            code_map.push((start_offset & !0x3, length, kind));
+        }
+
+        Ok(Some(Self { code_map }))
+    }
+
+    /// Return the kind of code located at the given RVA, if it's in a code range.
+    pub fn code_kind(&self, rva: u32) -> Option<HybridCodeKind> {
+        self.code_map
+            .iter()
+            .find(|(start, length, _)| rva >= *start && rva - *start < *length)
+            .map(|(_, _, kind)| *kind)
+    }
+}
+
+/// Extract the exports seen by ARM64EC and x64 code from an ARM64X PE, as
+/// (name, RVA) pairs.
+///
+/// ARM64X images ship two export tables. The PE header references the native
+/// one, while the one used by the ARM64EC view is swapped in at load time
+/// through ARM64X dynamic relocations applied to the PE header.
+pub fn extract_ec_exports(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(String, u32)>> {
+    let export_directory_entry_offset =
+        data_directory_entry_offset(pe, IMAGE_DIRECTORY_ENTRY_EXPORT);
+
+    // Apply ARM64X relocations to the export directory entry
+    let mut export_directory_entry: [u8; 8] = read_bytes(
+        pe_data,
+        export_directory_entry_offset,
+        2 * std::mem::size_of::<u32>(),
+    )?
+    .try_into()?;
+    let mut relocated = false;
+    for (rva, value) in arm64x_header_fixups(pe, pe_data)? {
+        for (i, byte) in value.into_iter().enumerate() {
+            let byte_rva = rva as usize + i;
+            if byte_rva >= export_directory_entry_offset
+                && byte_rva < export_directory_entry_offset + export_directory_entry.len()
+            {
+                export_directory_entry[byte_rva - export_directory_entry_offset] = byte;
+                relocated = true;
+            }
+        }
+    }
+    if !relocated {
+        // Both views share the same export table
+        return Ok(vec![]);
+    }
+
+    let export_directory_rva = u32::from_le_bytes(export_directory_entry[..4].try_into()?);
+    let export_directory_size = u32::from_le_bytes(export_directory_entry[4..].try_into()?);
+    parse_export_directory(pe, pe_data, export_directory_rva, export_directory_size)
+}
+
+/// Compute the file offset of a given data directory entry in the optional header
+fn data_directory_entry_offset(pe: &pe::PE<'_>, entry_index: usize) -> usize {
+    const SIZEOF_PE_SIGNATURE_AND_COFF_HEADER: usize = 4 + 20;
+    const DATA_DIRECTORIES_OFFSET_PE32: usize = 0x60;
+    const DATA_DIRECTORIES_OFFSET_PE32_PLUS: usize = 0x70;
+
+    let data_directories_offset = if pe.is_64 {
+        DATA_DIRECTORIES_OFFSET_PE32_PLUS
+    } else {
+        DATA_DIRECTORIES_OFFSET_PE32
+    };
+
+    pe.header.dos_header.pe_pointer as usize
+        + SIZEOF_PE_SIGNATURE_AND_COFF_HEADER
+        + data_directories_offset
+        + entry_index * 2 * std::mem::size_of::<u32>()
+}
+
+/// Collect the ARM64X fixups that target the PE header, as (RVA, new bytes) pairs.
+///
+/// Headers are mapped as-is, so RVAs returned here are also file offsets.
+fn arm64x_header_fixups(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(u32, Vec<u8>)>> {
+    let load_config = match pe.load_config_data.as_ref() {
+        Some(load_config) => &load_config.directory,
+        None => return Ok(vec![]),
+    };
+    let (dvrt_offset, dvrt_section) = match (
+        load_config.dynamic_value_reloc_table_offset,
+        load_config.dynamic_value_reloc_table_section,
+    ) {
+        (Some(offset), Some(section)) if section != 0 => (offset, section),
+        _ => return Ok(vec![]),
+    };
+    let dvrt_section = pe
+        .sections
+        .get(dvrt_section as usize - 1)
+        .ok_or(WinDiffError::HybridMetadataParsingError)?;
+    let size_of_headers = pe
+        .header
+        .optional_header
+        .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)?
+        .windows_fields
+        .size_of_headers;
+
+    // IMAGE_DYNAMIC_RELOCATION_TABLE
+    let table_offset = (dvrt_section.pointer_to_raw_data + dvrt_offset) as usize;
+    let table_version = read_u32(pe_data, table_offset)?;
+    let table_size = read_u32(pe_data, table_offset + 4)? as usize;
+    if table_version != 1 {
+        log::debug!("Unsupported DVRT version: {}", table_version);
+        return Ok(vec![]);
+    }
+
+    let mut result = vec![];
+    let mut cursor = table_offset + 8;
+    let table_end = cursor + table_size;
+    while cursor < table_end {
+        // IMAGE_DYNAMIC_RELOCATION64
+        let symbol = read_u64(pe_data, cursor)?;
+        let base_reloc_size = read_u32(pe_data, cursor + 8)? as usize;
+        cursor += 12;
+        let relocs_end = cursor + base_reloc_size;
+        if symbol != IMAGE_DYNAMIC_RELOCATION_ARM64X {
+            cursor = relocs_end;
+            continue;
+        }
+
+        // IMAGE_BASE_RELOCATION blocks
+        while cursor < relocs_end {
+            let page_rva = read_u32(pe_data, cursor)?;
+            let block_size = read_u32(pe_data, cursor + 4)? as usize;
+            if block_size < 8 {
+                return Err(WinDiffError::HybridMetadataParsingError);
+            }
+            let block_end = cursor + block_size;
+            let mut entry_cursor = cursor + 8;
+            while entry_cursor + 2 <= block_end {
+                let entry = read_u16(pe_data, entry_cursor)?;
+                entry_cursor += 2;
+                let rva = page_rva + (entry & 0xfff) as u32;
+                let fixup_type = (entry >> 12) & 0x3;
+                let meta = (entry >> 14) & 0x3;
+                match fixup_type {
+                    IMAGE_DVRT_ARM64X_FIXUP_TYPE_ZEROFILL => {
+                        // Null entries are only used as padding
+                        if rva < size_of_headers && entry != 0 {
+                            result.push((rva, vec![0; 1 << meta]));
+                        }
+                    }
+                    IMAGE_DVRT_ARM64X_FIXUP_TYPE_VALUE => {
+                        let value_size = 1_usize << meta;
+                        let value = read_bytes(pe_data, entry_cursor, value_size)?;
+                        if rva < size_of_headers {
+                            result.push((rva, value.to_vec()));
+                        }
+                        // Values are padded to 2-byte boundaries
+                        entry_cursor += value_size.max(2);
+                    }
+                    IMAGE_DVRT_ARM64X_FIXUP_TYPE_DELTA => {
+                        // Deltas are only used for pointers, which we don't need
+                        entry_cursor += 2;
+                    }
+                    _ => return Err(WinDiffError::HybridMetadataParsingError),
+                }
+            }
+            cursor = block_end;
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse an `IMAGE_EXPORT_DIRECTORY` and return named, non-forwarded exports
+fn parse_export_directory(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    export_directory_rva: u32,
+    export_directory_size: u32,
+) -> Result<Vec<(String, u32)>> {
+    let export_directory_offset = rva_to_offset(export_directory_rva as usize, pe)?;
+    let number_of_functions = read_u32(pe_data, export_directory_offset + 20)?;
+    let number_of_names = read_u32(pe_data, export_directory_offset + 24)?;
+    let functions_offset = rva_to_offset(
+        read_u32(pe_data, export_directory_offset + 28)? as usize,
+        pe,
+    )?;
+    let names_offset = rva_to_offset(
+        read_u32(pe_data, export_directory_offset + 32)? as usize,
+        pe,
+    )?;
+    let ordinals_offset = rva_to_offset(
+        read_u32(pe_data, export_directory_offset + 36)? as usize,
+        pe,
+    )?;
+
+    let mut result = Vec::with_capacity(number_of_names as usize);
+    for i in 0..number_of_names as usize {
+        let name_rva = read_u32(pe_data, names_offset + i * std::mem::size_of::<u32>())?;
+        let ordinal = read_u16(pe_data, ordinals_offset + i * std::mem::size_of::<u16>())?;
+        if u32::from(ordinal) >= number_of_functions {
+            return Err(WinDiffError::HybridMetadataParsingError);
+        }
+        let function_rva = read_u32(
+            pe_data,
+            functions_offset + ordinal as usize * std::mem::size_of::<u32>(),
+        )?;
+        // Skip forwarders, which point into the export directory
+        if function_rva >= export_directory_rva
+            && function_rva < export_directory_rva + export_directory_size
+        {
+            continue;
+        }
+
+        let name = read_c_string(pe_data, rva_to_offset(name_rva as usize, pe)?)?;
+        result.push((name, function_rva));
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,276 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction, Mnemonic, OpKind, Register};
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    imports::{import_address_table, resolve_import_thunk, ImportedFunction},
+    pe_utils::{read_bytes, rva_to_offset},
+};
+
+/// Extract the call edges of the given procedures (RVA -> (name, length)), as
+/// (caller, callee) pairs. Direct calls, tail calls and calls to imported
+/// functions are recorded, as long as their target can be symbolized.
+pub fn extract_call_graph(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    procedures: &BTreeMap<u32, (String, u32)>,
+) -> Result<Vec<(String, String)>> {
+    let is_amd64 = if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+        true
+    } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+        false
+    } else {
+        log::debug!("Unsupported architecture, call graph won't be extracted");
+        return Ok(vec![]);
+    };
+    log::trace!("Extracting call graph");
+
+    let mut call_targets = CallTargets::new(pe, pe_data, symbols, procedures);
+    let mut result = vec![];
+    for (function_rva, (function_name, function_size)) in procedures {
+        let function_code =
+            match rva_to_offset(*function_rva as usize, pe).and_then(|function_offset| {
+                read_bytes(pe_data, function_offset, *function_size as usize)
+            }) {
+                Ok(function_code) => function_code,
+                Err(err) => {
+                    log::debug!("Failed to read function '{}': {}", function_name, err);
+                    continue;
+                }
+            };
+
+        let callees = if is_amd64 {
+            find_callees_amd64(
+                pe.image_base,
+                &mut call_targets,
+                *function_rva,
+                function_code,
+            )
+        } else {
+            find_callees_arm64(&mut call_targets, *function_rva, function_code)
+        };
+        result.extend(
+            callees
+                .into_iter()
+                .map(|callee_name| (function_name.clone(), callee_name)),
+        );
+    }
+
+    Ok(result)
+}
+
+/// Symbolizes call targets
+struct CallTargets<'p, 'd> {
+    pe: &'p pe::PE<'d>,
+    pe_data: &'d [u8],
+    import_address_table: BTreeMap<u32, ImportedFunction>,
+    /// Function RVA -> function name (`None` for targets that couldn't be
+    /// symbolized)
+    functions: BTreeMap<u32, Option<String>>,
+}
+
+impl<'p, 'd> CallTargets<'p, 'd> {
+    fn new(
+        pe: &'p pe::PE<'d>,
+        pe_data: &'d [u8],
+        symbols: &BTreeMap<u32, String>,
+        procedures: &BTreeMap<u32, (String, u32)>,
+    ) -> Self {
+        let mut functions: BTreeMap<u32, Option<String>> = symbols
+            .iter()
+            .filter(|(symbol_rva, _)| **symbol_rva != 0)
+            .map(|(symbol_rva, symbol_name)| (*symbol_rva, Some(symbol_name.clone())))
+            .collect();
+        // Prefer procedure names, which match the callers' names
+        functions.extend(procedures.iter().map(|(function_rva, (function_name, _))| {
+            (*function_rva, Some(function_name.clone()))
+        }));
+
+        Self {
+            pe,
+            pe_data,
+            import_address_table: import_address_table(pe),
+            functions,
+        }
+    }
+
+    /// Get the name of the function imported through the given IAT slot
+    fn resolve_iat_slot(&self, iat_slot_rva: u32) -> Option<String> {
+        self.import_address_table
+            .get(&iat_slot_rva)
+            .map(|imported_function| imported_function.function.clone())
+    }
+
+    /// Get the name of the function located at the given RVA (or of the
+    /// imported function, if it's an import thunk)
+    fn resolve_function(&mut self, function_rva: u32) -> Option<String> {
+        let (pe, pe_data, import_address_table) =
+            (self.pe, self.pe_data, &self.import_address_table);
+        self.functions
+            .entry(function_rva)
+            .or_insert_with(|| {
+                resolve_import_thunk(pe, pe_data, import_address_table, function_rva)
+                    .map(|imported_function| imported_function.function.clone())
+            })
+            .clone()
+    }
+}
+
+/// Find the functions called by an AMD64 function. We want to match call
+/// sites such as:
+/// call FUNCTION ; or jmp FUNCTION, for tail calls
+/// or:
+/// call qword ptr [rip+IAT_SLOT]
+/// or:
+/// mov rax, qword ptr [rip+IAT_SLOT]
+/// ...
+/// call rax ; or a call to the CFG dispatch routine
+fn find_callees_amd64(
+    image_base: u64,
+    call_targets: &mut CallTargets<'_, '_>,
+    function_rva: u32,
+    function_code: &[u8],
+) -> Vec<String> {
+    let function_address = image_base + u64::from(function_rva);
+    let function_range = function_address..function_address + function_code.len() as u64;
+    // Registers loaded from IAT slots -> imported function name
+    let mut loaded_imports: BTreeMap<Register, String> = BTreeMap::new();
+    let mut result = vec![];
+
+    let mut decoder = Decoder::with_ip(64, function_code, function_address, DecoderOptions::NONE);
+    let mut instruction = Instruction::default();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+
+        let callee_name = match instruction.flow_control() {
+            FlowControl::Call | FlowControl::UnconditionalBranch
+                if !function_range.contains(&instruction.near_branch_target()) =>
+            {
+                let target_rva = instruction.near_branch_target().wrapping_sub(image_base) as u32;
+                call_targets.resolve_function(target_rva)
+            }
+            FlowControl::IndirectCall | FlowControl::IndirectBranch => {
+                if instruction.is_ip_rel_memory_operand() {
+                    let memory_rva =
+                        instruction.ip_rel_memory_address().wrapping_sub(image_base) as u32;
+                    // Calls to the CFG dispatch routine use RAX as target
+                    call_targets
+                        .resolve_iat_slot(memory_rva)
+                        .or_else(|| loaded_imports.get(&Register::RAX).cloned())
+                } else if instruction.op0_kind() == OpKind::Register {
+                    loaded_imports
+                        .get(&instruction.op0_register().full_register())
+                        .cloned()
+                } else {
+                    None
+                }
+            }
+            _ => {
+                if instruction.op0_kind() == OpKind::Register {
+                    let register = instruction.op0_register().full_register();
+                    let imported_function = if instruction.mnemonic() == Mnemonic::Mov
+                        && instruction.is_ip_rel_memory_operand()
+                    {
+                        let memory_rva =
+                            instruction.ip_rel_memory_address().wrapping_sub(image_base) as u32;
+                        call_targets.resolve_iat_slot(memory_rva)
+                    } else {
+                        None
+                    };
+                    match imported_function {
+                        Some(imported_function) => {
+                            loaded_imports.insert(register, imported_function)
+                        }
+                        None => loaded_imports.remove(&register),
+                    };
+                }
+                continue;
+            }
+        };
+
+        if matches!(
+            instruction.flow_control(),
+            FlowControl::Call | FlowControl::IndirectCall
+        ) {
+            loaded_imports.clear();
+        }
+        result.extend(callee_name);
+    }
+
+    result
+}
+
+/// Find the functions called by an ARM64 function. We want to match call
+/// sites such as:
+/// BL FUNCTION ; or B FUNCTION, for tail calls
+/// or:
+/// ADRP Xn, IAT_SLOT@PAGE
+/// LDR Xn, [Xn, IAT_SLOT@PAGEOFF]
+/// BLR Xn ; or BR Xn
+fn find_callees_arm64(
+    call_targets: &mut CallTargets<'_, '_>,
+    function_rva: u32,
+    function_code: &[u8],
+) -> Vec<String> {
+    let function_range = function_rva..function_rva + function_code.len() as u32;
+    // Values loaded into registers with ADRP
+    let mut page_addresses: [Option<u32>; 32] = [None; 32];
+    // Registers loaded from IAT slots
+    let mut loaded_imports: [Option<String>; 32] = Default::default();
+    let mut result = vec![];
+
+    for (i, instruction_bytes) in function_code.chunks_exact(4).enumerate() {
+        let instruction_rva = function_rva + 4 * i as u32;
+        let instruction = u32::from_le_bytes([
+            instruction_bytes[0],
+            instruction_bytes[1],
+            instruction_bytes[2],
+            instruction_bytes[3],
+        ]);
+        let rd = (instruction & 0x1f) as usize;
+        let rn = ((instruction >> 5) & 0x1f) as usize;
+
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/BL--Branch-with-Link-
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/B--Branch-
+        if instruction & 0x7c00_0000 == 0x1400_0000 {
+            // Sign-extend the 26-bit immediate
+            let imm = ((instruction & 0x3ff_ffff) << 6) as i32 >> 4;
+            let target_rva = instruction_rva.wrapping_add(imm as u32);
+            if !function_range.contains(&target_rva) {
+                result.extend(call_targets.resolve_function(target_rva));
+            }
+        }
+        // BLR Xn and BR Xn
+        else if instruction & 0xffdf_fc1f == 0xd61f_0000 {
+            result.extend(loaded_imports[rn].clone());
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/ADRP--Form-PC-relative-address-to-4KB-page-
+        else if instruction & 0x9f00_0000 == 0x9000_0000 {
+            let immlo = (instruction >> 29) & 0x3;
+            let immhi = (instruction >> 5) & 0x7_ffff;
+            // Sign-extend the 21-bit immediate and convert it to a page offset
+            let imm = (((immhi << 2) | immlo) << 11) as i32 >> 11;
+            page_addresses[rd] = Some((instruction_rva & !0xfff).wrapping_add((imm << 12) as u32));
+            loaded_imports[rd] = None;
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/LDR--immediate---Load-Register--immediate--
+        else if instruction & 0xffc0_0000 == 0xf940_0000 {
+            let imm12 = (instruction >> 10) & 0xfff;
+            loaded_imports[rd] = page_addresses[rn].and_then(|page_address| {
+                call_targets.resolve_iat_slot(page_address.wrapping_add(imm12 * 8))
+            });
+            page_addresses[rd] = None;
+        }
+        // Consider that other instructions overwrite their first operand
+        else {
+            page_addresses[rd] = None;
+            loaded_imports[rd] = None;
+        }
+    }
+
+    result
+}
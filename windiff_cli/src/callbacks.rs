@@ -0,0 +1,80 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+
+use crate::{
+    error::Result,
+    pe_utils::{is_code_rva, read_pointer_as_rva, rva_to_offset, symbolize_unknown_rva},
+};
+
+/// Name of the kernel callback table, referenced by the PEB's
+/// `KernelCallbackTable` field
+const KERNEL_CALLBACK_TABLE_NAME: &str = "apfnDispatch";
+
+/// Extract the kernel callback table found in a given PE (i.e., user32.dll), as
+/// a list of callback indexes and names. Returns an empty list if the PE
+/// doesn't contain such table.
+pub fn extract_callbacks(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(u32, String)>> {
+    // Find the callback table
+    let callback_table_rva = match symbols.iter().find(|(_, symbol_name)| {
+        // 32-bit binaries use decorated names
+        symbol_name.trim_start_matches('_') == KERNEL_CALLBACK_TABLE_NAME
+    }) {
+        Some((symbol_rva, _)) => *symbol_rva,
+        None => {
+            log::debug!("Kernel callback table not found");
+            return Ok(vec![]);
+        }
+    };
+    log::trace!("Extracting kernel callbacks");
+
+    extract_callbacks_from_table(pe, pe_data, symbols, callback_table_rva)
+}
+
+/// Walk a table of function pointers and symbolize its slots.
+///
+/// The table is considered to end at the next symbol, or at the first slot that
+/// doesn't point to code.
+pub fn extract_callbacks_from_table(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    callback_table_rva: u32,
+) -> Result<Vec<(u32, String)>> {
+    let pointer_size = if pe.is_64 {
+        std::mem::size_of::<u64>()
+    } else {
+        std::mem::size_of::<u32>()
+    } as u32;
+    let next_symbol_rva = symbols
+        .range(callback_table_rva + 1..)
+        .next()
+        .map(|(symbol_rva, _)| *symbol_rva)
+        .unwrap_or(u32::MAX);
+    let callback_table_offset = rva_to_offset(callback_table_rva as usize, pe)?;
+
+    let mut result = vec![];
+    for callback_index in 0.. {
+        let slot_rva = callback_table_rva + callback_index * pointer_size;
+        if slot_rva >= next_symbol_rva {
+            break;
+        }
+
+        let slot_offset = callback_table_offset + (callback_index * pointer_size) as usize;
+        let callback_rva = match read_pointer_as_rva(pe, pe_data, slot_offset)? {
+            Some(callback_rva) if is_code_rva(pe, callback_rva) => callback_rva,
+            _ => break,
+        };
+        let callback_name = symbols
+            .get(&callback_rva)
+            .cloned()
+            .unwrap_or_else(|| symbolize_unknown_rva(pe, symbols, callback_rva));
+        result.push((callback_index, callback_name));
+    }
+
+    Ok(result)
+}
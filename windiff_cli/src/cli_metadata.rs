@@ -0,0 +1,836 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::{Result, WinDiffError},
+    pe_utils::{read_bytes, read_c_string, read_u16, read_u32, read_u64, rva_to_offset},
+};
+
+/// "BSJB"
+const METADATA_SIGNATURE: u32 = 0x424a_5342;
+const HEAP_SIZES_LARGE_STRINGS: u8 = 0x1;
+const HEAP_SIZES_LARGE_GUIDS: u8 = 0x2;
+const HEAP_SIZES_LARGE_BLOBS: u8 = 0x4;
+const HEAP_SIZES_EXTRA_DATA: u8 = 0x40;
+/// Maximum nesting of types (and type signatures) we're willing to follow
+const MAX_TYPE_DEPTH: usize = 16;
+
+// Metadata tables (ECMA-335, II.22)
+const TABLE_COUNT: usize = 64;
+const MODULE: usize = 0x00;
+const TYPE_REF: usize = 0x01;
+const TYPE_DEF: usize = 0x02;
+const FIELD_PTR: usize = 0x03;
+const FIELD: usize = 0x04;
+const METHOD_PTR: usize = 0x05;
+const METHOD_DEF: usize = 0x06;
+const PARAM_PTR: usize = 0x07;
+const PARAM: usize = 0x08;
+const INTERFACE_IMPL: usize = 0x09;
+const MEMBER_REF: usize = 0x0a;
+const CONSTANT: usize = 0x0b;
+const CUSTOM_ATTRIBUTE: usize = 0x0c;
+const FIELD_MARSHAL: usize = 0x0d;
+const DECL_SECURITY: usize = 0x0e;
+const CLASS_LAYOUT: usize = 0x0f;
+const FIELD_LAYOUT: usize = 0x10;
+const STAND_ALONE_SIG: usize = 0x11;
+const EVENT_MAP: usize = 0x12;
+const EVENT_PTR: usize = 0x13;
+const EVENT: usize = 0x14;
+const PROPERTY_MAP: usize = 0x15;
+const PROPERTY_PTR: usize = 0x16;
+const PROPERTY: usize = 0x17;
+const METHOD_SEMANTICS: usize = 0x18;
+const METHOD_IMPL: usize = 0x19;
+const MODULE_REF: usize = 0x1a;
+const TYPE_SPEC: usize = 0x1b;
+const IMPL_MAP: usize = 0x1c;
+const FIELD_RVA: usize = 0x1d;
+const ENC_LOG: usize = 0x1e;
+const ENC_MAP: usize = 0x1f;
+const ASSEMBLY: usize = 0x20;
+const ASSEMBLY_PROCESSOR: usize = 0x21;
+const ASSEMBLY_OS: usize = 0x22;
+const ASSEMBLY_REF: usize = 0x23;
+const ASSEMBLY_REF_PROCESSOR: usize = 0x24;
+const ASSEMBLY_REF_OS: usize = 0x25;
+const FILE: usize = 0x26;
+const EXPORTED_TYPE: usize = 0x27;
+const MANIFEST_RESOURCE: usize = 0x28;
+const NESTED_CLASS: usize = 0x29;
+const GENERIC_PARAM: usize = 0x2a;
+const METHOD_SPEC: usize = 0x2b;
+const GENERIC_PARAM_CONSTRAINT: usize = 0x2c;
+/// Placeholder for unused coded index tags (this table is always empty)
+const UNUSED: usize = TABLE_COUNT - 1;
+
+// Coded indexes (ECMA-335, II.24.2.6)
+const TYPE_DEF_OR_REF: &[usize] = &[TYPE_DEF, TYPE_REF, TYPE_SPEC];
+const HAS_CONSTANT: &[usize] = &[FIELD, PARAM, PROPERTY];
+const HAS_CUSTOM_ATTRIBUTE: &[usize] = &[
+    METHOD_DEF,
+    FIELD,
+    TYPE_REF,
+    TYPE_DEF,
+    PARAM,
+    INTERFACE_IMPL,
+    MEMBER_REF,
+    MODULE,
+    DECL_SECURITY,
+    PROPERTY,
+    EVENT,
+    STAND_ALONE_SIG,
+    MODULE_REF,
+    TYPE_SPEC,
+    ASSEMBLY,
+    ASSEMBLY_REF,
+    FILE,
+    EXPORTED_TYPE,
+    MANIFEST_RESOURCE,
+    GENERIC_PARAM,
+    GENERIC_PARAM_CONSTRAINT,
+    METHOD_SPEC,
+];
+const HAS_FIELD_MARSHAL: &[usize] = &[FIELD, PARAM];
+const HAS_DECL_SECURITY: &[usize] = &[TYPE_DEF, METHOD_DEF, ASSEMBLY];
+const MEMBER_REF_PARENT: &[usize] = &[TYPE_DEF, TYPE_REF, MODULE_REF, METHOD_DEF, TYPE_SPEC];
+const HAS_SEMANTICS: &[usize] = &[EVENT, PROPERTY];
+const METHOD_DEF_OR_REF: &[usize] = &[METHOD_DEF, MEMBER_REF];
+const MEMBER_FORWARDED: &[usize] = &[FIELD, METHOD_DEF];
+const IMPLEMENTATION: &[usize] = &[FILE, ASSEMBLY_REF, EXPORTED_TYPE];
+const CUSTOM_ATTRIBUTE_TYPE: &[usize] = &[UNUSED, UNUSED, METHOD_DEF, MEMBER_REF, UNUSED];
+const RESOLUTION_SCOPE: &[usize] = &[MODULE, MODULE_REF, ASSEMBLY_REF, TYPE_REF];
+const TYPE_OR_METHOD_DEF: &[usize] = &[TYPE_DEF, METHOD_DEF];
+
+/// Type of a metadata table column
+#[derive(Copy, Clone)]
+enum Column {
+    U16,
+    U32,
+    String,
+    Guid,
+    Blob,
+    Index(usize),
+    CodedIndex(&'static [usize]),
+}
+
+/// Managed type defined by a module's metadata
+#[derive(Serialize, Debug, Default)]
+pub struct ManagedType {
+    /// "class", "interface", "struct", "enum" or "delegate"
+    pub kind: String,
+    pub base_type: Option<String>,
+    /// Implemented interfaces
+    pub interfaces: BTreeSet<String>,
+    /// Fields, formatted as "name : type"
+    pub fields: BTreeSet<String>,
+    /// Methods, formatted as "name(parameter types) : return type"
+    pub methods: BTreeSet<String>,
+    /// Custom attributes' types
+    pub attributes: BTreeSet<String>,
+}
+
+/// Extract the types defined by the CLI metadata of a given PE (i.e., managed
+/// assemblies and WinMD files), as (full type name, type) pairs. Returns an
+/// empty list for native PEs.
+pub fn extract_managed_types(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+) -> Result<Vec<(String, ManagedType)>> {
+    let cor20_header_rva = match pe
+        .header
+        .optional_header
+        .and_then(|optional_header| {
+            optional_header
+                .data_directories
+                .get_clr_runtime_header()
+                .copied()
+        })
+        .filter(|data_directory| data_directory.virtual_address != 0)
+    {
+        Some(data_directory) => data_directory.virtual_address,
+        None => return Ok(vec![]),
+    };
+    log::trace!("Extracting CLI metadata");
+
+    // IMAGE_COR20_HEADER.MetaData
+    let cor20_header_offset = rva_to_offset(cor20_header_rva as usize, pe)?;
+    let metadata_rva = read_u32(pe_data, cor20_header_offset + 8)?;
+    let metadata_size = read_u32(pe_data, cor20_header_offset + 12)?;
+    let metadata_offset = rva_to_offset(metadata_rva as usize, pe)?;
+    let metadata = read_bytes(pe_data, metadata_offset, metadata_size as usize)?;
+
+    Metadata::parse(metadata)?.managed_types()
+}
+
+/// Parsed CLI metadata root, with its heaps and tables
+struct Metadata<'d> {
+    strings: &'d [u8],
+    blobs: &'d [u8],
+    tables: &'d [u8],
+    heap_sizes: u8,
+    row_counts: [u32; TABLE_COUNT],
+    table_offsets: [usize; TABLE_COUNT],
+    row_sizes: [usize; TABLE_COUNT],
+}
+
+impl<'d> Metadata<'d> {
+    fn parse(metadata: &'d [u8]) -> Result<Self> {
+        if read_u32(metadata, 0)? != METADATA_SIGNATURE {
+            return Err(WinDiffError::CliMetadataParsingError);
+        }
+
+        // Streams
+        let version_length = read_u32(metadata, 12)? as usize;
+        let stream_count = read_u16(metadata, 16 + version_length + 2)?;
+        let mut stream_header_offset = 16 + version_length + 4;
+        let mut streams = BTreeMap::new();
+        for _ in 0..stream_count {
+            let stream_offset = read_u32(metadata, stream_header_offset)? as usize;
+            let stream_size = read_u32(metadata, stream_header_offset + 4)? as usize;
+            let stream_name = read_c_string(metadata, stream_header_offset + 8)?;
+            // Names are NUL-terminated and padded to 4 bytes
+            stream_header_offset += 8 + ((stream_name.len() + 4) & !3);
+            streams.insert(
+                stream_name,
+                read_bytes(metadata, stream_offset, stream_size)?,
+            );
+        }
+        let strings = streams.get("#Strings").copied().unwrap_or_default();
+        let blobs = streams.get("#Blob").copied().unwrap_or_default();
+        let tables = streams
+            .get("#~")
+            .or_else(|| streams.get("#-"))
+            .copied()
+            .ok_or(WinDiffError::CliMetadataParsingError)?;
+
+        // Tables header
+        let heap_sizes = read_bytes(tables, 6, 1)?[0];
+        let valid_tables = read_u64(tables, 8)?;
+        let mut row_counts = [0; TABLE_COUNT];
+        let mut row_count_offset = 24;
+        for (table, row_count) in row_counts.iter_mut().enumerate() {
+            if valid_tables & (1 << table) != 0 {
+                *row_count = read_u32(tables, row_count_offset)?;
+                row_count_offset += 4;
+            }
+        }
+        if heap_sizes & HEAP_SIZES_EXTRA_DATA != 0 {
+            row_count_offset += 4;
+        }
+
+        let mut metadata = Self {
+            strings,
+            blobs,
+            tables,
+            heap_sizes,
+            row_counts,
+            table_offsets: [0; TABLE_COUNT],
+            row_sizes: [0; TABLE_COUNT],
+        };
+        // Tables are stored contiguously, in order
+        let mut table_offset = row_count_offset;
+        for table in 0..TABLE_COUNT {
+            if metadata.row_counts[table] == 0 {
+                continue;
+            }
+            let row_size = table_schema(table)
+                .ok_or(WinDiffError::CliMetadataParsingError)?
+                .iter()
+                .map(|column| metadata.column_size(*column))
+                .sum();
+            metadata.table_offsets[table] = table_offset;
+            metadata.row_sizes[table] = row_size;
+            table_offset += row_size * metadata.row_counts[table] as usize;
+        }
+
+        Ok(metadata)
+    }
+
+    /// List the types defined by the module
+    fn managed_types(&self) -> Result<Vec<(String, ManagedType)>> {
+        // Nested type -> enclosing type
+        let mut enclosing_types = BTreeMap::new();
+        for row in 1..=self.row_counts[NESTED_CLASS] {
+            let nested_class = self.row(NESTED_CLASS, row)?;
+            enclosing_types.insert(nested_class[0], nested_class[1]);
+        }
+        let type_names = (1..=self.row_counts[TYPE_DEF])
+            .map(|row| self.type_def_name(&enclosing_types, row, 0))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut managed_types: Vec<ManagedType> = (0..type_names.len())
+            .map(|_| ManagedType::default())
+            .collect();
+        // Method -> declaring type, used to resolve attribute constructors
+        let mut method_owners = BTreeMap::new();
+        for (type_index, managed_type) in managed_types.iter_mut().enumerate() {
+            let row = type_index as u32 + 1;
+            let type_def = self.row(TYPE_DEF, row)?;
+            let flags = type_def[0];
+
+            managed_type.base_type = match self.coded_index(TYPE_DEF_OR_REF, type_def[3]) {
+                Some((table, base_row)) => Some(self.type_name(&type_names, table, base_row, 0)?),
+                None => None,
+            };
+            managed_type.kind = match managed_type.base_type.as_deref() {
+                // TypeAttributes.Interface
+                _ if flags & 0x20 != 0 => "interface",
+                Some("System.Enum") => "enum",
+                Some("System.ValueType") => "struct",
+                Some("System.MulticastDelegate") => "delegate",
+                _ => "class",
+            }
+            .to_string();
+
+            // Fields
+            let (first_field, last_field) = self.member_range(TYPE_DEF, row, 4, FIELD)?;
+            for field_index in first_field..last_field {
+                let field = self.row(FIELD, self.member_row(FIELD, field_index)?)?;
+                let field_type = SignatureDecoder::new(self, &type_names, self.blob(field[2])?)
+                    .decode_field_signature()
+                    .unwrap_or_else(|| "?".to_string());
+                managed_type
+                    .fields
+                    .insert(format!("{} : {}", self.string(field[1])?, field_type));
+            }
+
+            // Methods
+            let (first_method, last_method) = self.member_range(TYPE_DEF, row, 5, METHOD_DEF)?;
+            for method_index in first_method..last_method {
+                let method_row = self.member_row(METHOD_DEF, method_index)?;
+                let method = self.row(METHOD_DEF, method_row)?;
+                let (return_type, parameter_types) =
+                    SignatureDecoder::new(self, &type_names, self.blob(method[4])?)
+                        .decode_method_signature(0)
+                        .unwrap_or_else(|| ("?".to_string(), vec![]));
+                managed_type.methods.insert(format!(
+                    "{}({}) : {}",
+                    self.string(method[3])?,
+                    parameter_types.join(", "),
+                    return_type
+                ));
+                method_owners.insert(method_row, type_index);
+            }
+        }
+
+        // Implemented interfaces
+        for row in 1..=self.row_counts[INTERFACE_IMPL] {
+            let interface_impl = self.row(INTERFACE_IMPL, row)?;
+            if let (Some(managed_type), Some((table, interface_row))) = (
+                (interface_impl[0] as usize)
+                    .checked_sub(1)
+                    .and_then(|type_index| managed_types.get_mut(type_index)),
+                self.coded_index(TYPE_DEF_OR_REF, interface_impl[1]),
+            ) {
+                managed_type.interfaces.insert(self.type_name(
+                    &type_names,
+                    table,
+                    interface_row,
+                    0,
+                )?);
+            }
+        }
+
+        // Custom attributes applied to types
+        for row in 1..=self.row_counts[CUSTOM_ATTRIBUTE] {
+            let custom_attribute = self.row(CUSTOM_ATTRIBUTE, row)?;
+            let type_index = match self.coded_index(HAS_CUSTOM_ATTRIBUTE, custom_attribute[0]) {
+                Some((TYPE_DEF, type_row)) => type_row as usize - 1,
+                _ => continue,
+            };
+            // Attributes are identified by their constructor
+            let attribute_type = match self.coded_index(CUSTOM_ATTRIBUTE_TYPE, custom_attribute[1])
+            {
+                Some((METHOD_DEF, method_row)) => method_owners
+                    .get(&method_row)
+                    .map(|owner_index| type_names[*owner_index].clone()),
+                Some((MEMBER_REF, member_row)) => {
+                    let member_ref = self.row(MEMBER_REF, member_row)?;
+                    match self.coded_index(MEMBER_REF_PARENT, member_ref[0]) {
+                        Some((table, parent_row)) => {
+                            Some(self.type_name(&type_names, table, parent_row, 0)?)
+                        }
+                        None => None,
+                    }
+                }
+                _ => None,
+            };
+            if let (Some(managed_type), Some(attribute_type)) =
+                (managed_types.get_mut(type_index), attribute_type)
+            {
+                managed_type.attributes.insert(attribute_type);
+            }
+        }
+
+        Ok(type_names
+            .into_iter()
+            .zip(managed_types)
+            // Pseudo-type holding global members
+            .filter(|(type_name, _)| type_name != "<Module>")
+            .collect())
+    }
+
+    /// Compute the [first, last) range of member indexes (see `member_row`)
+    /// of `member_table` owned by a row of `owner_table`, whose first member
+    /// is given by the column `list_column`
+    fn member_range(
+        &self,
+        owner_table: usize,
+        owner_row: u32,
+        list_column: usize,
+        member_table: usize,
+    ) -> Result<(u32, u32)> {
+        let member_count = match pointer_table(member_table) {
+            Some(pointer_table) if self.row_counts[pointer_table] != 0 => {
+                self.row_counts[pointer_table]
+            }
+            _ => self.row_counts[member_table],
+        };
+        let first_member = self.row(owner_table, owner_row)?[list_column].max(1);
+        let last_member = if owner_row < self.row_counts[owner_table] {
+            self.row(owner_table, owner_row + 1)?[list_column]
+        } else {
+            member_count + 1
+        };
+
+        Ok((
+            first_member,
+            last_member.min(member_count + 1).max(first_member),
+        ))
+    }
+
+    /// Convert a member index into a row of `member_table`. Uncompressed
+    /// ("#-") metadata can list members through an indirection table, in which
+    /// case member indexes are rows of that table.
+    fn member_row(&self, member_table: usize, member_index: u32) -> Result<u32> {
+        match pointer_table(member_table) {
+            Some(pointer_table) if self.row_counts[pointer_table] != 0 => {
+                Ok(self.row(pointer_table, member_index)?[0])
+            }
+            _ => Ok(member_index),
+        }
+    }
+
+    /// Format the name of a type referenced by a coded index
+    fn type_name(
+        &self,
+        type_names: &[String],
+        table: usize,
+        row: u32,
+        depth: usize,
+    ) -> Result<String> {
+        match table {
+            TYPE_DEF => type_names
+                .get(row as usize - 1)
+                .cloned()
+                .ok_or(WinDiffError::CliMetadataParsingError),
+            TYPE_REF => self.type_ref_name(row, depth),
+            TYPE_SPEC => {
+                let type_spec = self.row(TYPE_SPEC, row)?;
+                Ok(
+                    SignatureDecoder::new(self, type_names, self.blob(type_spec[0])?)
+                        .decode_type(depth + 1)
+                        .unwrap_or_else(|| "?".to_string()),
+                )
+            }
+            _ => Ok("?".to_string()),
+        }
+    }
+
+    /// Format the full name of a type definition ("Namespace.Type" or
+    /// "Namespace.EnclosingType/Type")
+    fn type_def_name(
+        &self,
+        enclosing_types: &BTreeMap<u32, u32>,
+        row: u32,
+        depth: usize,
+    ) -> Result<String> {
+        let type_def = self.row(TYPE_DEF, row)?;
+        let type_name = self.string(type_def[1])?;
+        match enclosing_types.get(&row) {
+            Some(enclosing_row) if depth < MAX_TYPE_DEPTH => Ok(format!(
+                "{}/{}",
+                self.type_def_name(enclosing_types, *enclosing_row, depth + 1)?,
+                type_name
+            )),
+            _ => Ok(qualify_type_name(&self.string(type_def[2])?, &type_name)),
+        }
+    }
+
+    /// Format the full name of a type reference
+    fn type_ref_name(&self, row: u32, depth: usize) -> Result<String> {
+        let type_ref = self.row(TYPE_REF, row)?;
+        let type_name = self.string(type_ref[1])?;
+        match self.coded_index(RESOLUTION_SCOPE, type_ref[0]) {
+            // Nested type reference
+            Some((TYPE_REF, enclosing_row)) if depth < MAX_TYPE_DEPTH => Ok(format!(
+                "{}/{}",
+                self.type_ref_name(enclosing_row, depth + 1)?,
+                type_name
+            )),
+            _ => Ok(qualify_type_name(&self.string(type_ref[2])?, &type_name)),
+        }
+    }
+
+    /// Read a row (1-based) of a table, as a list of column values
+    fn row(&self, table: usize, row: u32) -> Result<Vec<u32>> {
+        if row == 0 || row > self.row_counts[table] {
+            return Err(WinDiffError::CliMetadataParsingError);
+        }
+        let schema = table_schema(table).ok_or(WinDiffError::CliMetadataParsingError)?;
+
+        let mut column_offset =
+            self.table_offsets[table] + (row as usize - 1) * self.row_sizes[table];
+        let mut result = Vec::with_capacity(schema.len());
+        for column in schema {
+            let column_size = self.column_size(*column);
+            result.push(if column_size == 2 {
+                u32::from(read_u16(self.tables, column_offset)?)
+            } else {
+                read_u32(self.tables, column_offset)?
+            });
+            column_offset += column_size;
+        }
+
+        Ok(result)
+    }
+
+    /// Decode a coded index into a (table, row) pair. Returns `None` for null
+    /// indexes.
+    fn coded_index(&self, tables: &[usize], value: u32) -> Option<(usize, u32)> {
+        let tag_bits = coded_index_tag_bits(tables);
+        let table = *tables.get((value & ((1 << tag_bits) - 1)) as usize)?;
+        let row = value >> tag_bits;
+
+        (row != 0 && table != UNUSED).then_some((table, row))
+    }
+
+    fn column_size(&self, column: Column) -> usize {
+        let heap_index_size = |large_heap_flag| {
+            if self.heap_sizes & large_heap_flag != 0 {
+                4
+            } else {
+                2
+            }
+        };
+
+        match column {
+            Column::U16 => 2,
+            Column::U32 => 4,
+            Column::String => heap_index_size(HEAP_SIZES_LARGE_STRINGS),
+            Column::Guid => heap_index_size(HEAP_SIZES_LARGE_GUIDS),
+            Column::Blob => heap_index_size(HEAP_SIZES_LARGE_BLOBS),
+            Column::Index(table) => {
+                if self.row_counts[table] < 1 << 16 {
+                    2
+                } else {
+                    4
+                }
+            }
+            Column::CodedIndex(tables) => {
+                let max_row_count = tables
+                    .iter()
+                    .map(|table| self.row_counts[*table])
+                    .max()
+                    .unwrap_or_default();
+                if max_row_count < 1 << (16 - coded_index_tag_bits(tables)) {
+                    2
+                } else {
+                    4
+                }
+            }
+        }
+    }
+
+    /// Read a string from the `#Strings` heap
+    fn string(&self, index: u32) -> Result<String> {
+        read_c_string(self.strings, index as usize)
+    }
+
+    /// Read a blob from the `#Blob` heap
+    fn blob(&self, index: u32) -> Result<&'d [u8]> {
+        let mut reader = SignatureReader::new(
+            self.blobs
+                .get(index as usize..)
+                .ok_or(WinDiffError::OutOfBoundsRead(index as usize))?,
+        );
+        let blob_size = reader
+            .read_compressed()
+            .ok_or(WinDiffError::OutOfBoundsRead(index as usize))?;
+
+        read_bytes(
+            self.blobs,
+            index as usize + reader.position,
+            blob_size as usize,
+        )
+    }
+}
+
+/// Decoder for signature blobs (ECMA-335, II.23.2)
+struct SignatureDecoder<'m, 'd> {
+    metadata: &'m Metadata<'d>,
+    type_names: &'m [String],
+    reader: SignatureReader<'d>,
+}
+
+impl<'m, 'd> SignatureDecoder<'m, 'd> {
+    fn new(metadata: &'m Metadata<'d>, type_names: &'m [String], signature: &'d [u8]) -> Self {
+        Self {
+            metadata,
+            type_names,
+            reader: SignatureReader::new(signature),
+        }
+    }
+
+    fn decode_field_signature(&mut self) -> Option<String> {
+        // FIELD
+        if self.reader.read_u8()? != 0x06 {
+            return None;
+        }
+        self.decode_type(0)
+    }
+
+    /// Decode a method signature into a (return type, parameter types) pair
+    fn decode_method_signature(&mut self, depth: usize) -> Option<(String, Vec<String>)> {
+        let calling_convention = self.reader.read_u8()?;
+        // GENERIC
+        if calling_convention & 0x10 != 0 {
+            let _generic_parameter_count = self.reader.read_compressed()?;
+        }
+        let parameter_count = self.reader.read_compressed()?;
+        let return_type = self.decode_type(depth)?;
+        let parameter_types = (0..parameter_count)
+            .map(|_| self.decode_type(depth))
+            .collect::<Option<Vec<_>>>()?;
+
+        Some((return_type, parameter_types))
+    }
+
+    fn decode_type(&mut self, depth: usize) -> Option<String> {
+        if depth >= MAX_TYPE_DEPTH {
+            return None;
+        }
+
+        let element_type = self.reader.read_u8()?;
+        let type_name = match element_type {
+            0x01 => "void".to_string(),
+            0x02 => "bool".to_string(),
+            0x03 => "char".to_string(),
+            0x04 => "sbyte".to_string(),
+            0x05 => "byte".to_string(),
+            0x06 => "short".to_string(),
+            0x07 => "ushort".to_string(),
+            0x08 => "int".to_string(),
+            0x09 => "uint".to_string(),
+            0x0a => "long".to_string(),
+            0x0b => "ulong".to_string(),
+            0x0c => "float".to_string(),
+            0x0d => "double".to_string(),
+            0x0e => "string".to_string(),
+            // PTR
+            0x0f => format!("{}*", self.decode_type(depth + 1)?),
+            // BYREF
+            0x10 => format!("{}&", self.decode_type(depth + 1)?),
+            // VALUETYPE and CLASS
+            0x11 | 0x12 => self.decode_type_def_or_ref(depth)?,
+            // VAR
+            0x13 => format!("!{}", self.reader.read_compressed()?),
+            // ARRAY
+            0x14 => {
+                let element_type = self.decode_type(depth + 1)?;
+                let rank = self.reader.read_compressed()?;
+                for _ in 0..2 {
+                    // Sizes, then lower bounds
+                    let count = self.reader.read_compressed()?;
+                    for _ in 0..count {
+                        self.reader.read_compressed()?;
+                    }
+                }
+                format!(
+                    "{}[{}]",
+                    element_type,
+                    ",".repeat(rank.saturating_sub(1) as usize)
+                )
+            }
+            // GENERICINST
+            0x15 => {
+                let _class_or_value_type = self.reader.read_u8()?;
+                let generic_type = self.decode_type_def_or_ref(depth)?;
+                let argument_count = self.reader.read_compressed()?;
+                let arguments = (0..argument_count)
+                    .map(|_| self.decode_type(depth + 1))
+                    .collect::<Option<Vec<_>>>()?;
+                format!("{}<{}>", generic_type, arguments.join(", "))
+            }
+            0x16 => "TypedReference".to_string(),
+            0x18 => "IntPtr".to_string(),
+            0x19 => "UIntPtr".to_string(),
+            // FNPTR
+            0x1b => {
+                let (return_type, parameter_types) = self.decode_method_signature(depth + 1)?;
+                format!("fnptr {}({})", return_type, parameter_types.join(", "))
+            }
+            0x1c => "object".to_string(),
+            // SZARRAY
+            0x1d => format!("{}[]", self.decode_type(depth + 1)?),
+            // MVAR
+            0x1e => format!("!!{}", self.reader.read_compressed()?),
+            // CMOD_REQD and CMOD_OPT
+            0x1f | 0x20 => {
+                let _modifier = self.reader.read_compressed()?;
+                self.decode_type(depth + 1)?
+            }
+            // SENTINEL
+            0x41 => "...".to_string(),
+            // PINNED
+            0x45 => self.decode_type(depth + 1)?,
+            _ => return None,
+        };
+
+        Some(type_name)
+    }
+
+    /// Decode a `TypeDefOrRefOrSpecEncoded` value
+    fn decode_type_def_or_ref(&mut self, depth: usize) -> Option<String> {
+        let encoded = self.reader.read_compressed()?;
+        let (table, row) = self.metadata.coded_index(TYPE_DEF_OR_REF, encoded)?;
+        self.metadata
+            .type_name(self.type_names, table, row, depth + 1)
+            .ok()
+    }
+}
+
+/// Cursor over a signature blob
+struct SignatureReader<'d> {
+    data: &'d [u8],
+    position: usize,
+}
+
+impl<'d> SignatureReader<'d> {
+    fn new(data: &'d [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.position)?;
+        self.position += 1;
+        Some(value)
+    }
+
+    /// Read a compressed unsigned integer (ECMA-335, II.23.2)
+    fn read_compressed(&mut self) -> Option<u32> {
+        let first_byte = u32::from(self.read_u8()?);
+        if first_byte & 0x80 == 0 {
+            Some(first_byte)
+        } else if first_byte & 0xc0 == 0x80 {
+            Some((first_byte & 0x3f) << 8 | u32::from(self.read_u8()?))
+        } else if first_byte & 0xe0 == 0xc0 {
+            let mut value = first_byte & 0x1f;
+            for _ in 0..3 {
+                value = value << 8 | u32::from(self.read_u8()?);
+            }
+            Some(value)
+        } else {
+            None
+        }
+    }
+}
+
+fn qualify_type_name(namespace: &str, type_name: &str) -> String {
+    if namespace.is_empty() {
+        type_name.to_string()
+    } else {
+        format!("{}.{}", namespace, type_name)
+    }
+}
+
+/// Number of bits used to encode the table of a coded index
+fn coded_index_tag_bits(tables: &[usize]) -> u32 {
+    usize::BITS - (tables.len() - 1).leading_zeros()
+}
+
+/// Indirection table that uncompressed metadata can use to list the rows of a
+/// table, if any. These tables aren't part of ECMA-335.
+fn pointer_table(table: usize) -> Option<usize> {
+    match table {
+        FIELD => Some(FIELD_PTR),
+        METHOD_DEF => Some(METHOD_PTR),
+        PARAM => Some(PARAM_PTR),
+        EVENT => Some(EVENT_PTR),
+        PROPERTY => Some(PROPERTY_PTR),
+        _ => None,
+    }
+}
+
+/// Columns of each metadata table (ECMA-335, II.22)
+fn table_schema(table: usize) -> Option<&'static [Column]> {
+    use Column::*;
+
+    Some(match table {
+        MODULE => &[U16, String, Guid, Guid, Guid],
+        TYPE_REF => &[CodedIndex(RESOLUTION_SCOPE), String, String],
+        TYPE_DEF => &[
+            U32,
+            String,
+            String,
+            CodedIndex(TYPE_DEF_OR_REF),
+            Index(FIELD),
+            Index(METHOD_DEF),
+        ],
+        FIELD_PTR => &[Index(FIELD)],
+        FIELD => &[U16, String, Blob],
+        METHOD_PTR => &[Index(METHOD_DEF)],
+        METHOD_DEF => &[U32, U16, U16, String, Blob, Index(PARAM)],
+        PARAM_PTR => &[Index(PARAM)],
+        PARAM => &[U16, U16, String],
+        INTERFACE_IMPL => &[Index(TYPE_DEF), CodedIndex(TYPE_DEF_OR_REF)],
+        MEMBER_REF => &[CodedIndex(MEMBER_REF_PARENT), String, Blob],
+        CONSTANT => &[U16, CodedIndex(HAS_CONSTANT), Blob],
+        CUSTOM_ATTRIBUTE => &[
+            CodedIndex(HAS_CUSTOM_ATTRIBUTE),
+            CodedIndex(CUSTOM_ATTRIBUTE_TYPE),
+            Blob,
+        ],
+        FIELD_MARSHAL => &[CodedIndex(HAS_FIELD_MARSHAL), Blob],
+        DECL_SECURITY => &[U16, CodedIndex(HAS_DECL_SECURITY), Blob],
+        CLASS_LAYOUT => &[U16, U32, Index(TYPE_DEF)],
+        FIELD_LAYOUT => &[U32, Index(FIELD)],
+        STAND_ALONE_SIG => &[Blob],
+        EVENT_MAP => &[Index(TYPE_DEF), Index(EVENT)],
+        EVENT_PTR => &[Index(EVENT)],
+        EVENT => &[U16, String, CodedIndex(TYPE_DEF_OR_REF)],
+        PROPERTY_MAP => &[Index(TYPE_DEF), Index(PROPERTY)],
+        PROPERTY_PTR => &[Index(PROPERTY)],
+        PROPERTY => &[U16, String, Blob],
+        METHOD_SEMANTICS => &[U16, Index(METHOD_DEF), CodedIndex(HAS_SEMANTICS)],
+        METHOD_IMPL => &[
+            Index(TYPE_DEF),
+            CodedIndex(METHOD_DEF_OR_REF),
+            CodedIndex(METHOD_DEF_OR_REF),
+        ],
+        MODULE_REF => &[String],
+        TYPE_SPEC => &[Blob],
+        IMPL_MAP => &[U16, CodedIndex(MEMBER_FORWARDED), String, Index(MODULE_REF)],
+        FIELD_RVA => &[U32, Index(FIELD)],
+        ENC_LOG => &[U32, U32],
+        ENC_MAP => &[U32],
+        ASSEMBLY => &[U32, U16, U16, U16, U16, U32, Blob, String, String],
+        ASSEMBLY_PROCESSOR => &[U32],
+        ASSEMBLY_OS => &[U32, U32, U32],
+        ASSEMBLY_REF => &[U16, U16, U16, U16, U32, Blob, String, String, Blob],
+        ASSEMBLY_REF_PROCESSOR => &[U32, Index(ASSEMBLY_REF)],
+        ASSEMBLY_REF_OS => &[U32, U32, U32, Index(ASSEMBLY_REF)],
+        FILE => &[U32, String, Blob],
+        EXPORTED_TYPE => &[U32, U32, String, String, CodedIndex(IMPLEMENTATION)],
+        MANIFEST_RESOURCE => &[U32, U32, String, CodedIndex(IMPLEMENTATION)],
+        NESTED_CLASS => &[Index(TYPE_DEF), Index(TYPE_DEF)],
+        GENERIC_PARAM => &[U16, U16, CodedIndex(TYPE_OR_METHOD_DEF), String],
+        METHOD_SPEC => &[CodedIndex(METHOD_DEF_OR_REF), Blob],
+        GENERIC_PARAM_CONSTRAINT => &[Index(GENERIC_PARAM), CodedIndex(TYPE_DEF_OR_REF)],
+        _ => return None,
+    })
+}
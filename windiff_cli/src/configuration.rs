@@ -1,6 +1,7 @@
 use crate::error::Result;
 
 use enumflags2::{bitflags, BitFlag, BitFlags};
+use regex::RegexSet;
 use serde::{de::DeserializeOwned, Deserialize, Deserializer};
 use std::{collections::BTreeMap, path::Path};
 use tokio::{fs::File, io::AsyncReadExt};
@@ -36,11 +37,41 @@ pub enum OSArchitecture {
 pub struct BinaryDescription {
     #[serde(deserialize_with = "deserialize_flags")]
     pub extracted_information: BinaryExtractedInformation,
+    /// Minimum length of the strings harvested with `STRINGS`
+    #[serde(default)]
+    pub min_string_length: Option<usize>,
+    /// Filters applied to the extracted names
+    #[serde(default)]
+    pub filters: BinaryFilters,
+}
+
+/// Per-kind filters, used to produce focused databases
+#[derive(Deserialize, Default)]
+pub struct BinaryFilters {
+    #[serde(default)]
+    pub symbols: NameFilter,
+    #[serde(default)]
+    pub types: NameFilter,
+    #[serde(default)]
+    pub modules: NameFilter,
+    #[serde(default)]
+    pub exports: NameFilter,
+}
+
+/// Regular expressions that select which names are kept
+#[derive(Deserialize, Default)]
+pub struct NameFilter {
+    /// If not empty, only names that match one of these are kept
+    #[serde(default, deserialize_with = "deserialize_regex_set")]
+    pub include: RegexSet,
+    /// Names that match one of these are discarded
+    #[serde(default, deserialize_with = "deserialize_regex_set")]
+    pub exclude: RegexSet,
 }
 
 /// Bitflag used to define which information to extract from binaries
 #[bitflags]
-#[repr(u16)]
+#[repr(u32)]
 #[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "SCREAMING_SNAKE_CASE")]
 pub enum BinaryExtractedInformationFlags {
@@ -49,6 +80,23 @@ pub enum BinaryExtractedInformationFlags {
     Modules,
     Types,
     Syscalls,
+    Callbacks,
+    Rpc,
+    Typelib,
+    MessageTable,
+    Apiset,
+    ManagedTypes,
+    EtwTracelogging,
+    EtwManifest,
+    Strings,
+    Features,
+    Wnf,
+    PoolTags,
+    FunctionHashes,
+    CallGraph,
+    Vtables,
+    Rtti,
+    TlsExceptions,
 }
 
 pub type BinaryExtractedInformation = BitFlags<BinaryExtractedInformationFlags>;
@@ -67,6 +115,12 @@ impl WinDiffConfiguration {
     }
 }
 
+impl NameFilter {
+    pub fn is_match(&self, name: &str) -> bool {
+        (self.include.is_empty() || self.include.is_match(name)) && !self.exclude.is_match(name)
+    }
+}
+
 impl OSArchitecture {
     pub const fn to_str(self) -> &'static str {
         match self {
@@ -97,3 +151,11 @@ where
     let flags = Vec::<T>::deserialize(d)?;
     Ok(BitFlags::from_iter(flags))
 }
+
+pub fn deserialize_regex_set<'de, D>(d: D) -> std::result::Result<RegexSet, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let patterns = Vec::<String>::deserialize(d)?;
+    RegexSet::new(patterns).map_err(serde::de::Error::custom)
+}
@@ -3,24 +3,45 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use async_compression::tokio::write::GzipEncoder;
+use async_compression::tokio::{bufread::GzipDecoder, write::GzipEncoder};
 use futures::StreamExt;
 use goblin::{pe, Object};
-use serde::Serialize;
+use serde::{de::DeserializeOwned, Serialize};
 use tokio::{
     fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncReadExt, AsyncWriteExt, BufReader},
 };
 
 use crate::{
-    configuration::{
-        BinaryExtractedInformation, BinaryExtractedInformationFlags, WinDiffConfiguration,
-    },
+    apiset::{extract_api_set, ApiSetContract},
+    call_graph::extract_call_graph,
+    callbacks::extract_callbacks,
+    cli_metadata::{extract_managed_types, ManagedType},
+    configuration::{BinaryDescription, BinaryExtractedInformationFlags, WinDiffConfiguration},
+    dependency_graph::generate_dependency_graphs,
     error::{Result, WinDiffError},
+    etw_manifest::{extract_etw_manifests, EtwManifestProvider},
+    features::{extract_features, Feature},
+    function_hashes::{extract_function_hashes, FunctionHash},
+    message_table::extract_message_tables,
     pdb::Pdb,
+    pe_utils::delimit_functions,
+    pool_tags::extract_pool_tags,
     resym_frontend::WinDiffApp,
-    syscalls::extract_syscalls,
+    rpc::{extract_rpc_interfaces, RpcInterface},
+    rtti::{extract_rtti, RttiBaseClass},
+    strings::{extract_strings, BinaryString, DEFAULT_MIN_STRING_LENGTH},
+    synthesized_symbols::{
+        exported_function_symbols, exported_procedures, procedure_symbols, synthesize_procedures,
+    },
+    syscall_interface::generate_syscall_interfaces,
+    syscalls::{extract_ec_syscalls, extract_syscalls},
+    tls_exceptions::{extract_exception_directory, extract_tls_callbacks, ExceptionDirectory},
+    tracelogging::{extract_tracelogging, TraceLoggingProvider},
+    typelib::{extract_typelib, ComType},
+    vtables::extract_vtables,
     winbindex::DownloadedPEVersion,
+    wnf::{extract_wnf_state_names, WnfStateName},
 };
 
 /// Database index.
@@ -39,6 +60,9 @@ pub struct DatabaseIndex {
     pub binaries_with_symbols: BTreeMap<String, BTreeSet<String>>,
     pub binaries_with_modules: BTreeMap<String, BTreeSet<String>>,
     pub binaries_with_types: BTreeMap<String, BTreeSet<String>>,
+    /// Subset of `binaries_with_symbols` whose symbols were synthesized from
+    /// the PE's exported functions, because no PDB was available.
+    pub binaries_with_synthesized_symbols: BTreeMap<String, BTreeSet<String>>,
 }
 
 /// Per-OS-version sets of binaries that produced non-empty PDB-derived data,
@@ -49,6 +73,7 @@ pub struct BinariesWithInfo {
     pub symbols: BTreeMap<String, BTreeSet<String>>,
     pub modules: BTreeMap<String, BTreeSet<String>>,
     pub types: BTreeMap<String, BTreeSet<String>>,
+    pub synthesized_symbols: BTreeMap<String, BTreeSet<String>>,
 }
 
 impl BinariesWithInfo {
@@ -68,6 +93,9 @@ impl BinariesWithInfo {
         if presence.has_types {
             insert(&mut self.types);
         }
+        if presence.has_synthesized_symbols {
+            insert(&mut self.synthesized_symbols);
+        }
     }
 
     /// Merges another instance into this one (used to accumulate across the
@@ -82,6 +110,12 @@ impl BinariesWithInfo {
         for (suffix, binaries) in other.types {
             self.types.entry(suffix).or_default().extend(binaries);
         }
+        for (suffix, binaries) in other.synthesized_symbols {
+            self.synthesized_symbols
+                .entry(suffix)
+                .or_default()
+                .extend(binaries);
+        }
     }
 }
 
@@ -91,6 +125,7 @@ struct ExtractedInfoPresence {
     has_symbols: bool,
     has_modules: bool,
     has_types: bool,
+    has_synthesized_symbols: bool,
 }
 
 /// A version of Windows, defined as a triplet
@@ -115,6 +150,76 @@ pub struct BinaryDatabase {
     pub types: BTreeMap<String, String>,
     // Syscalls detected in the binary (for relevant executables)
     pub syscalls: BTreeMap<u32, String>,
+    /// Number of arguments passed on the stack to each syscall (for kernel
+    /// binaries). Arguments passed in registers (the first 4 on AMD64) aren't
+    /// counted, so this is only the full argument count on x86.
+    pub syscall_stack_argument_counts: BTreeMap<u32, u32>,
+    /// Syscalls whose implementation couldn't be symbolized with the PDB (for
+    /// kernel binaries)
+    pub unresolved_syscalls: BTreeSet<u32>,
+    /// Binary and function that implement each syscall (for win32k.sys, whose
+    /// service table may point at stubs that forward calls to other drivers)
+    pub syscall_implementations: BTreeMap<u32, SyscallImplementation>,
+    /// Whether each syscall is allowed by the filtered service tables used for
+    /// processes with syscall restrictions (syscall -> filtered table -> allowed)
+    pub syscall_filters: BTreeMap<u32, BTreeMap<String, bool>>,
+    /// Syscalls detected in the ARM64EC view of ARM64X binaries
+    pub ec_syscalls: BTreeMap<u32, String>,
+    /// Syscall stubs whose identifier differs between the native and ARM64EC
+    /// views (stub name -> identifiers)
+    pub syscall_id_mismatches: BTreeMap<String, SyscallIdMismatch>,
+    /// Kernel callbacks (for user32.dll)
+    pub callbacks: BTreeMap<u32, String>,
+    /// RPC server interfaces ("<interface UUID> v<major>.<minor>" -> interface)
+    pub rpc_interfaces: BTreeMap<String, RpcInterface>,
+    /// COM types described by embedded type libraries ("Library.Type" -> type)
+    pub com_types: BTreeMap<String, ComType>,
+    /// Message tables (language -> message identifier -> message text)
+    pub message_tables: BTreeMap<u16, BTreeMap<String, String>>,
+    /// API set schema (for apisetschema.dll, contract name -> contract)
+    pub api_set: BTreeMap<String, ApiSetContract>,
+    /// Types defined by CLI metadata (for managed assemblies and WinMD files,
+    /// full type name -> type)
+    pub managed_types: BTreeMap<String, ManagedType>,
+    /// TraceLogging providers and their events (provider name -> provider)
+    pub tracelogging_providers: BTreeMap<String, TraceLoggingProvider>,
+    /// Manifest-based ETW providers described by WEVT_TEMPLATE resources
+    /// (provider GUID -> provider)
+    pub etw_manifest_providers: BTreeMap<String, EtwManifestProvider>,
+    /// Strings found in read-only data (string -> string information)
+    pub strings: BTreeMap<String, BinaryString>,
+    /// WIL staged features (feature name -> feature)
+    pub features: BTreeMap<String, Feature>,
+    /// WNF state names (name -> state name)
+    pub wnf_state_names: BTreeMap<String, WnfStateName>,
+    /// Pool tags passed to pool routines (pool tag -> calling functions)
+    pub pool_tags: BTreeMap<String, BTreeSet<String>>,
+    /// Fingerprints of the functions' code (function name -> hash)
+    pub function_hashes: BTreeMap<String, FunctionHash>,
+    /// Call graph (caller -> callees)
+    pub call_graph: BTreeMap<String, BTreeSet<String>>,
+    /// C++ virtual function tables (class name -> symbolized slots)
+    pub vtables: BTreeMap<String, Vec<String>>,
+    /// Class hierarchies described by RTTI (class name -> base classes)
+    pub class_hierarchies: BTreeMap<String, Vec<RttiBaseClass>>,
+    /// TLS callbacks, in the order they're called by the loader
+    pub tls_callbacks: Vec<String>,
+    /// Summary of the exception directory (for AMD64 and ARM64 binaries)
+    pub exception_directory: Option<ExceptionDirectory>,
+}
+
+/// Location of a syscall's implementation
+#[derive(Serialize, Debug)]
+pub struct SyscallImplementation {
+    pub binary: String,
+    pub function: String,
+}
+
+/// Identifiers of a syscall stub present in both views of an ARM64X binary
+#[derive(Serialize, Debug)]
+pub struct SyscallIdMismatch {
+    pub native_id: u32,
+    pub ec_id: u32,
 }
 
 #[derive(Serialize, Debug, Default)]
@@ -173,6 +278,10 @@ pub async fn generate_databases(
     if generate_index {
         // Generate database index
         generate_database_index(downloaded_binaries, &binaries_with_info, output_directory).await?;
+        // Join syscall databases into per-OS syscall interfaces
+        generate_syscall_interfaces(downloaded_binaries, output_directory).await?;
+        // Resolve binaries' imports into per-OS dependency graphs
+        generate_dependency_graphs(downloaded_binaries, output_directory).await?;
     }
 
     Ok(binaries_with_info)
@@ -180,7 +289,7 @@ pub async fn generate_databases(
 
 /// Builds the "version_update_architecture" suffix used for database file names
 /// and as the key shared with the frontend's `osVersionToPathSuffix`.
-fn os_path_suffix(pe_version: &DownloadedPEVersion) -> String {
+pub fn os_path_suffix(pe_version: &DownloadedPEVersion) -> String {
     format!(
         "{}_{}_{}",
         pe_version.os_version,
@@ -189,6 +298,11 @@ fn os_path_suffix(pe_version: &DownloadedPEVersion) -> String {
     )
 }
 
+/// Builds the path of the database generated for a given binary and OS version
+pub fn database_path(output_directory: &Path, binary_name: &str, os_suffix: &str) -> PathBuf {
+    output_directory.join(format!("{}_{}.json.gz", binary_name, os_suffix))
+}
+
 async fn generate_database_for_pe_version(
     cfg: &WinDiffConfiguration,
     windiff_app: &WinDiffApp,
@@ -222,11 +336,11 @@ async fn generate_database_for_pe_version(
             .binaries
             .get(&pe_version.original_name)
             .ok_or_else(|| WinDiffError::FileNotFoundInConfiguration)?;
-        let output_file = output_directory.join(format!(
-            "{}_{}.json.gz",
-            pe_version.original_name,
-            os_path_suffix(pe_version)
-        ));
+        let output_file = database_path(
+            output_directory,
+            &pe_version.original_name,
+            &os_path_suffix(pe_version),
+        );
 
         let presence = generate_database_for_pe(
             windiff_app,
@@ -234,7 +348,7 @@ async fn generate_database_for_pe_version(
             pe,
             &file_data,
             pdb,
-            &binary_desc.extracted_information,
+            binary_desc,
             output_file,
         )
         .await?;
@@ -250,15 +364,76 @@ async fn generate_database_for_pe(
     pe_version: &DownloadedPEVersion,
     pe: pe::PE<'_>,
     pe_data: &[u8],
-    pdb: Option<Pdb<'_>>,
-    extracted_information: &BinaryExtractedInformation,
+    mut pdb: Option<Pdb<'_>>,
+    binary_desc: &BinaryDescription,
     output_path: impl AsRef<Path>,
 ) -> Result<ExtractedInfoPresence> {
+    let extracted_information = &binary_desc.extracted_information;
     let mut database = BinaryDatabase::default();
     // Metadata
     database.metadata.name = pe_version.original_name.clone();
     database.metadata.version = pe_version.pe_version.clone();
     database.metadata.architecture = pe_version.architecture.to_str().to_string();
+    // Information kinds that depend on symbols or function boundaries, and
+    // can be extracted without a PDB
+    let needs_synthesized_symbols = extracted_information.intersects(
+        BinaryExtractedInformationFlags::Strings
+            | BinaryExtractedInformationFlags::PoolTags
+            | BinaryExtractedInformationFlags::TlsExceptions,
+    );
+    let needs_symbols = needs_synthesized_symbols
+        || extracted_information.intersects(
+            BinaryExtractedInformationFlags::Syscalls
+                | BinaryExtractedInformationFlags::Callbacks
+                | BinaryExtractedInformationFlags::Rpc
+                | BinaryExtractedInformationFlags::Features
+                | BinaryExtractedInformationFlags::Wnf
+                | BinaryExtractedInformationFlags::FunctionHashes
+                | BinaryExtractedInformationFlags::CallGraph
+                | BinaryExtractedInformationFlags::Vtables
+                | BinaryExtractedInformationFlags::Rtti,
+        );
+    let needs_procedures = extracted_information.intersects(
+        BinaryExtractedInformationFlags::FunctionHashes
+            | BinaryExtractedInformationFlags::CallGraph,
+    );
+    // Synthesize function names and boundaries from the PE itself if no PDB is
+    // available. This is best-effort and mustn't prevent the extraction of
+    // other information.
+    let has_pdb = pdb.is_some();
+    let synthesized_procedures = if !has_pdb && (needs_synthesized_symbols || needs_procedures) {
+        synthesize_procedures(&pe, pe_data).unwrap_or_else(|err| {
+            log::warn!("Failed to synthesize procedures: {}", err);
+            BTreeMap::new()
+        })
+    } else {
+        BTreeMap::new()
+    };
+    // Symbols are used by most information kinds, so they're extracted once,
+    // if needed
+    let symbols = match pdb.as_mut() {
+        Some(pdb) if needs_symbols => pdb.extract_symbols_with_offset(false)?,
+        None if needs_synthesized_symbols => procedure_symbols(&synthesized_procedures),
+        _ => BTreeMap::new(),
+    };
+    // Same for function boundaries. Without a PDB, only exported functions are
+    // hashed and put in the call graph, like for `DEBUG_SYMBOLS` ("sub_<rva>"
+    // names would make diffs useless)
+    let procedures = if needs_procedures {
+        match pdb.as_mut() {
+            Some(pdb) => delimit_functions(&pe, pdb.extract_functions()?),
+            None => exported_procedures(&pe, &synthesized_procedures),
+        }
+    } else {
+        BTreeMap::new()
+    };
+    // Names of the functions referenced by hashed functions and callers
+    let exported_symbols = if has_pdb {
+        BTreeMap::new()
+    } else {
+        procedure_symbols(&procedures)
+    };
+    let function_symbols = if has_pdb { &symbols } else { &exported_symbols };
 
     // Extract exports
     if extracted_information.contains(BinaryExtractedInformationFlags::Exports) {
@@ -268,12 +443,146 @@ async fn generate_database_for_pe(
             .filter_map(|exp| Some(exp.name?.to_string()))
             .collect();
     }
+    // Extract COM types from type libraries
+    if extracted_information.contains(BinaryExtractedInformationFlags::Typelib) {
+        for (type_name, com_type) in
+            unwrap_or_log_extraction_error("COM types", extract_typelib(&pe, pe_data))
+        {
+            database.com_types.entry(type_name).or_insert(com_type);
+        }
+    }
+    // Extract message tables
+    if extracted_information.contains(BinaryExtractedInformationFlags::MessageTable) {
+        for (language, message_id, message) in
+            unwrap_or_log_extraction_error("message tables", extract_message_tables(&pe, pe_data))
+        {
+            database
+                .message_tables
+                .entry(language)
+                .or_default()
+                .insert(format!("{:#010x}", message_id), message);
+        }
+    }
+    // Extract API set schema
+    if extracted_information.contains(BinaryExtractedInformationFlags::Apiset) {
+        database.api_set =
+            unwrap_or_log_extraction_error("API set schema", extract_api_set(&pe, pe_data))
+                .into_iter()
+                .collect();
+    }
+    // Extract managed types from CLI metadata
+    if extracted_information.contains(BinaryExtractedInformationFlags::ManagedTypes) {
+        database.managed_types =
+            unwrap_or_log_extraction_error("managed types", extract_managed_types(&pe, pe_data))
+                .into_iter()
+                .collect();
+    }
+    // Extract TraceLogging metadata
+    if extracted_information.contains(BinaryExtractedInformationFlags::EtwTracelogging) {
+        database.tracelogging_providers = unwrap_or_log_extraction_error(
+            "TraceLogging metadata",
+            extract_tracelogging(&pe, pe_data),
+        )
+        .into_iter()
+        .collect();
+    }
+    // Extract manifest-based ETW providers
+    if extracted_information.contains(BinaryExtractedInformationFlags::EtwManifest) {
+        for (provider_guid, provider) in
+            unwrap_or_log_extraction_error("ETW manifests", extract_etw_manifests(&pe, pe_data))
+        {
+            database
+                .etw_manifest_providers
+                .entry(provider_guid)
+                .or_insert(provider);
+        }
+    }
+    // Extract strings, attributed to debug symbols if available
+    if extracted_information.contains(BinaryExtractedInformationFlags::Strings) {
+        let min_length = binary_desc
+            .min_string_length
+            .unwrap_or(DEFAULT_MIN_STRING_LENGTH);
+        for (string, string_info) in unwrap_or_log_extraction_error(
+            "strings",
+            extract_strings(&pe, pe_data, &symbols, min_length),
+        ) {
+            database.strings.entry(string).or_insert(string_info);
+        }
+    }
+    // Extract pool tags, attributed to debug symbols if available
+    if extracted_information.contains(BinaryExtractedInformationFlags::PoolTags) {
+        for (pool_tag, function_name) in
+            unwrap_or_log_extraction_error("pool tags", extract_pool_tags(&pe, pe_data, &symbols))
+        {
+            database
+                .pool_tags
+                .entry(pool_tag)
+                .or_default()
+                .insert(function_name);
+        }
+    }
+    // Extract TLS callbacks and exception directory, symbolized with debug
+    // symbols if available
+    if extracted_information.contains(BinaryExtractedInformationFlags::TlsExceptions) {
+        database.tls_callbacks = extract_tls_callbacks(&pe, &symbols);
+        database.exception_directory = unwrap_or_log_extraction_error(
+            "exception directory",
+            extract_exception_directory(&pe, pe_data, &symbols),
+        );
+    }
+    // Extract debug symbols, or exported functions if no PDB is available
+    // ("sub_<rva>" names would make diffs useless)
+    if extracted_information.contains(BinaryExtractedInformationFlags::DebugSymbols) {
+        database.symbols = match pdb.as_mut() {
+            Some(pdb) => pdb.extract_symbols(true, &binary_desc.filters.symbols)?,
+            None => exported_function_symbols(&pe)
+                .into_iter()
+                .filter(|name| {
+                    binary_desc
+                        .filters
+                        .symbols
+                        .is_match(name.strip_suffix("()").unwrap_or(name))
+                })
+                .collect(),
+        };
+    }
+    // Hash functions' code
+    if extracted_information.contains(BinaryExtractedInformationFlags::FunctionHashes) {
+        for (function_name, function_hash) in unwrap_or_log_extraction_error(
+            "function hashes",
+            extract_function_hashes(&pe, pe_data, function_symbols, &procedures),
+        ) {
+            database
+                .function_hashes
+                .entry(function_name)
+                .or_insert(function_hash);
+        }
+    }
+    // Extract call graph
+    if extracted_information.contains(BinaryExtractedInformationFlags::CallGraph) {
+        for (caller_name, callee_name) in
+            extract_call_graph(&pe, pe_data, function_symbols, &procedures)?
+        {
+            database
+                .call_graph
+                .entry(caller_name)
+                .or_default()
+                .insert(callee_name);
+        }
+    }
+    // Extract syscalls from the ARM64EC view, which only relies on exports.
+    // The view is optional, don't let it fail the database
+    if extracted_information.contains(BinaryExtractedInformationFlags::Syscalls) {
+        database.ec_syscalls = match extract_ec_syscalls(&pe, pe_data) {
+            Ok(ec_syscalls) => ec_syscalls.into_iter().collect(),
+            Err(err) => {
+                log::debug!("Failed to extract ARM64EC syscalls: {}", err);
+                BTreeMap::new()
+            }
+        };
+    }
     // Extract information from the PDB file if available
     if let Some(mut pdb) = pdb {
-        // Extract debug symbols
-        if extracted_information.contains(BinaryExtractedInformationFlags::DebugSymbols) {
-            database.symbols = pdb.extract_symbols(true)?;
-        }
         // Extract compiled modules
         if extracted_information.contains(BinaryExtractedInformationFlags::Modules) {
             database.modules = pdb.extract_modules()?;
@@ -281,36 +590,153 @@ async fn generate_database_for_pe(
         // Extract debug types
         if extracted_information.contains(BinaryExtractedInformationFlags::Types) {
             database.types = windiff_app
-                .extract_types_from_pdb(&pdb.file_path)?
+                .extract_types_from_pdb(&pdb.file_path, &binary_desc.filters.types)?
                 .into_iter()
                 .collect();
         }
         // Extract syscalls
         if extracted_information.contains(BinaryExtractedInformationFlags::Syscalls) {
-            database.syscalls = extract_syscalls(pe, pe_data, &mut pdb)?
+            let extracted_syscalls = extract_syscalls(&pe, pe_data, &symbols)?;
+            database.syscalls = extracted_syscalls.syscalls.into_iter().collect();
+            database.syscall_stack_argument_counts = extracted_syscalls
+                .stack_argument_counts
+                .into_iter()
+                .collect();
+            database.unresolved_syscalls =
+                extracted_syscalls.unresolved_syscalls.into_iter().collect();
+            database.syscall_implementations = extracted_syscalls
+                .implementations
+                .into_iter()
+                .map(|(syscall_id, implementation)| {
+                    (
+                        syscall_id,
+                        SyscallImplementation {
+                            binary: implementation.module,
+                            function: implementation.function,
+                        },
+                    )
+                })
+                .collect();
+            for (filter_name, filter) in extracted_syscalls.filters {
+                for (syscall_id, allowed) in filter {
+                    database
+                        .syscall_filters
+                        .entry(syscall_id)
+                        .or_default()
+                        .insert(filter_name.clone(), allowed);
+                }
+            }
+            database.syscall_id_mismatches =
+                find_syscall_id_mismatches(&database.syscalls, &database.ec_syscalls);
+        }
+        // Extract kernel callbacks
+        if extracted_information.contains(BinaryExtractedInformationFlags::Callbacks) {
+            database.callbacks = extract_callbacks(&pe, pe_data, &symbols)?
+                .into_iter()
+                .collect();
+        }
+        // Extract RPC interfaces
+        if extracted_information.contains(BinaryExtractedInformationFlags::Rpc) {
+            for (interface_uuid, rpc_interface) in extract_rpc_interfaces(&pe, pe_data, &symbols)? {
+                // Several versions of an interface may be served by the same
+                // binary. Keep the first definition if a version is registered
+                // twice.
+                database
+                    .rpc_interfaces
+                    .entry(format!("{} v{}", interface_uuid, rpc_interface.version))
+                    .or_insert(rpc_interface);
+            }
+        }
+        // Extract WIL features
+        if extracted_information.contains(BinaryExtractedInformationFlags::Features) {
+            database.features = extract_features(&pe, pe_data, &mut pdb, &symbols)?
                 .into_iter()
                 .collect();
         }
+        // Extract C++ vtables
+        if extracted_information.contains(BinaryExtractedInformationFlags::Vtables) {
+            for (class_name, slots) in extract_vtables(&pe, pe_data, &symbols)? {
+                database.vtables.entry(class_name).or_insert(slots);
+            }
+        }
+        // Extract class hierarchies from RTTI
+        if extracted_information.contains(BinaryExtractedInformationFlags::Rtti) {
+            for (class_name, base_classes) in extract_rtti(&pe, pe_data, &symbols)? {
+                database
+                    .class_hierarchies
+                    .entry(class_name)
+                    .or_insert(base_classes);
+            }
+        }
+        // Extract WNF state names
+        if extracted_information.contains(BinaryExtractedInformationFlags::Wnf) {
+            for (name, state_name) in extract_wnf_state_names(&pe, pe_data, &symbols)? {
+                database.wnf_state_names.entry(name).or_insert(state_name);
+            }
+        }
     }
 
+    // Apply the binary's remaining filters (symbols and types are filtered
+    // during extraction)
+    let filters = &binary_desc.filters;
+    database
+        .exports
+        .retain(|name| filters.exports.is_match(name));
+    database
+        .modules
+        .retain(|name| filters.modules.is_match(name));
+
     let presence = ExtractedInfoPresence {
         has_symbols: !database.symbols.is_empty(),
         has_modules: !database.modules.is_empty(),
         has_types: !database.types.is_empty(),
+        has_synthesized_symbols: !has_pdb && !database.symbols.is_empty(),
     };
 
     // Serialize database
-    let json_data = serde_json::to_vec(&database)?;
-
-    // Create file and copy compressed JSON data
-    let output_file = File::create(output_path.as_ref()).await?;
-    let mut gz = GzipEncoder::new(output_file);
-    gz.write_all(json_data.as_slice()).await?;
-    gz.shutdown().await?;
+    write_compressed_json(&database, output_path.as_ref()).await?;
 
     Ok(presence)
 }
 
+/// Unwrap the result of an extraction that depends on resources or other
+/// optional structures, so that malformed data only empties the corresponding
+/// section instead of failing the whole database.
+fn unwrap_or_log_extraction_error<T: Default>(information_kind: &str, result: Result<T>) -> T {
+    result.unwrap_or_else(|err| {
+        log::warn!("Failed to extract {}: {}", information_kind, err);
+        T::default()
+    })
+}
+
+/// Find syscall stubs exported by both views of an ARM64X binary that don't
+/// share the same identifier.
+fn find_syscall_id_mismatches(
+    native_syscalls: &BTreeMap<u32, String>,
+    ec_syscalls: &BTreeMap<u32, String>,
+) -> BTreeMap<String, SyscallIdMismatch> {
+    let native_ids: BTreeMap<&String, u32> = native_syscalls
+        .iter()
+        .map(|(syscall_id, name)| (name, *syscall_id))
+        .collect();
+
+    ec_syscalls
+        .iter()
+        .filter_map(|(ec_id, name)| {
+            let native_id = *native_ids.get(name)?;
+            (native_id != *ec_id).then(|| {
+                (
+                    name.clone(),
+                    SyscallIdMismatch {
+                        native_id,
+                        ec_id: *ec_id,
+                    },
+                )
+            })
+        })
+        .collect()
+}
+
 pub async fn generate_database_index(
     downloaded_binaries: &[(DownloadedPEVersion, Option<PathBuf>)],
     binaries_with_info: &BinariesWithInfo,
@@ -337,13 +763,18 @@ pub async fn generate_database_index(
         binaries_with_symbols: binaries_with_info.symbols.clone(),
         binaries_with_modules: binaries_with_info.modules.clone(),
         binaries_with_types: binaries_with_info.types.clone(),
+        binaries_with_synthesized_symbols: binaries_with_info.synthesized_symbols.clone(),
     };
 
     // Serialize index
-    let json_data = serde_json::to_vec(&index)?;
+    write_compressed_json(&index, &output_directory.join("index.json.gz")).await
+}
+
+/// Serializes a value to JSON and writes it to a gzip-compressed file
+pub async fn write_compressed_json<T: Serialize>(value: &T, output_path: &Path) -> Result<()> {
+    let json_data = serde_json::to_vec(value)?;
 
     // Create file and copy compressed JSON data
-    let output_path = output_directory.join("index.json.gz");
     let output_file = File::create(output_path).await?;
     let mut gz = GzipEncoder::new(output_file);
     gz.write_all(json_data.as_slice()).await?;
@@ -351,3 +782,13 @@ pub async fn generate_database_index(
 
     Ok(())
 }
+
+/// Reads a gzip-compressed JSON file and deserializes its content
+pub async fn read_compressed_json<T: DeserializeOwned>(input_path: &Path) -> Result<T> {
+    let input_file = File::open(input_path).await?;
+    let mut gz = GzipDecoder::new(BufReader::new(input_file));
+    let mut json_data = vec![];
+    let _read_bytes = gz.read_to_end(&mut json_data).await?;
+
+    Ok(serde_json::from_slice(&json_data)?)
+}
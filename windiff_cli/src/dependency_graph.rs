@@ -0,0 +1,256 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use goblin::Object;
+use serde::Serialize;
+
+use crate::{
+    apiset::{extract_api_set, ApiSetContract},
+    database::{os_path_suffix, write_compressed_json},
+    error::{Result, WinDiffError},
+    imports::delay_load_imports,
+    winbindex::DownloadedPEVersion,
+};
+
+/// Binary that contains the API set schema
+const API_SET_SCHEMA_BINARY: &str = "apisetschema.dll";
+/// Prefixes of API set contract names
+const API_SET_CONTRACT_PREFIXES: [&str; 2] = ["api-", "ext-"];
+
+/// Dependency graph of the binaries of an OS version, with API set contracts
+/// resolved to their host DLLs.
+#[derive(Serialize, Debug, Default)]
+pub struct DependencyGraph {
+    /// Importing binary -> imported DLL -> dependency
+    pub dependencies: BTreeMap<String, BTreeMap<String, Dependency>>,
+    /// Reverse index: imported DLL -> imported function -> importing binaries
+    pub importers: BTreeMap<String, BTreeMap<String, BTreeSet<String>>>,
+}
+
+/// Edge of the dependency graph
+#[derive(Serialize, Debug, Default)]
+pub struct Dependency {
+    /// Imported functions
+    pub functions: BTreeSet<String>,
+    /// API set contracts the functions were imported through (empty for direct
+    /// imports)
+    pub contracts: BTreeSet<String>,
+    /// Set if some of the functions are imported through the delay-load import
+    /// directory
+    pub delay_loaded: bool,
+}
+
+/// Functions a binary imports from a DLL
+#[derive(Debug, Default)]
+struct DllImports {
+    functions: BTreeSet<String>,
+    delay_loaded: bool,
+}
+
+/// Imports and API set schemas collected from downloaded binaries, grouped by
+/// OS path suffix. Collected while PEs are available on disk, and turned into
+/// dependency graphs once all binaries have been processed.
+#[derive(Debug, Default)]
+pub struct BinaryDependencies {
+    /// OS suffix -> importing binary -> imported DLL -> imported functions
+    imports: BTreeMap<String, BTreeMap<String, BTreeMap<String, DllImports>>>,
+    /// OS suffix -> contract name -> contract
+    api_sets: BTreeMap<String, BTreeMap<String, ApiSetContract>>,
+}
+
+impl BinaryDependencies {
+    /// Parse the import tables (and API set schemas) of the given binaries.
+    /// Binaries that cannot be parsed are skipped.
+    pub async fn collect(downloaded_binaries: &[(DownloadedPEVersion, Option<PathBuf>)]) -> Self {
+        let mut result = Self::default();
+        for (pe_version, _) in downloaded_binaries {
+            if let Err(err) = result.collect_pe_version(pe_version).await {
+                log::warn!(
+                    "Failed to collect dependencies of {:?}: {}",
+                    pe_version.path,
+                    err
+                );
+            }
+        }
+
+        result
+    }
+
+    async fn collect_pe_version(&mut self, pe_version: &DownloadedPEVersion) -> Result<()> {
+        let file_data = tokio::fs::read(&pe_version.path).await?;
+        let pe = match Object::parse(&file_data)? {
+            Object::PE(pe) => pe,
+            _ => return Err(WinDiffError::UnsupportedExecutableFormat),
+        };
+        let os_suffix = os_path_suffix(pe_version);
+
+        if pe_version
+            .original_name
+            .eq_ignore_ascii_case(API_SET_SCHEMA_BINARY)
+        {
+            self.api_sets.insert(
+                os_suffix.clone(),
+                extract_api_set(&pe, &file_data)?.into_iter().collect(),
+            );
+        }
+
+        let binary_imports = self
+            .imports
+            .entry(os_suffix)
+            .or_default()
+            .entry(pe_version.original_name.to_lowercase())
+            .or_default();
+        for import in &pe.imports {
+            binary_imports
+                .entry(import.dll.to_lowercase())
+                .or_default()
+                .functions
+                .insert(import.name.to_string());
+        }
+        // Delay-loaded imports aren't required to load the binary, but they're
+        // commonly used for extension API sets
+        match delay_load_imports(&pe, &file_data) {
+            Ok(imported_functions) => {
+                for imported_function in imported_functions {
+                    let dll_imports = binary_imports
+                        .entry(imported_function.module.to_lowercase())
+                        .or_default();
+                    dll_imports.functions.insert(imported_function.function);
+                    dll_imports.delay_loaded = true;
+                }
+            }
+            Err(err) => log::debug!(
+                "Failed to parse delay-load imports of {:?}: {}",
+                pe_version.path,
+                err
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Merges another instance into this one (used to accumulate across the
+    /// per-binary passes of low-storage mode).
+    pub fn merge(&mut self, other: BinaryDependencies) {
+        for (os_suffix, binaries) in other.imports {
+            self.imports.entry(os_suffix).or_default().extend(binaries);
+        }
+        self.api_sets.extend(other.api_sets);
+    }
+
+    /// Write a dependency graph file for each OS version
+    pub async fn write_dependency_graphs(&self, output_directory: &Path) -> Result<()> {
+        for (os_suffix, imports) in &self.imports {
+            log::trace!("Generating dependency graph for '{}'", os_suffix);
+            let api_set = self.api_sets.get(os_suffix);
+            if api_set.is_none() {
+                log::debug!(
+                    "No API set schema for '{}', contracts won't be resolved",
+                    os_suffix
+                );
+            }
+
+            let dependency_graph = build_dependency_graph(imports, api_set);
+            write_compressed_json(
+                &dependency_graph,
+                &output_directory.join(format!("dependency_graph_{}.json.gz", os_suffix)),
+            )
+            .await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Generate a dependency graph file for each OS version, from downloaded
+/// binaries.
+pub async fn generate_dependency_graphs(
+    downloaded_binaries: &[(DownloadedPEVersion, Option<PathBuf>)],
+    output_directory: &Path,
+) -> Result<()> {
+    BinaryDependencies::collect(downloaded_binaries)
+        .await
+        .write_dependency_graphs(output_directory)
+        .await
+}
+
+fn build_dependency_graph(
+    imports: &BTreeMap<String, BTreeMap<String, DllImports>>,
+    api_set: Option<&BTreeMap<String, ApiSetContract>>,
+) -> DependencyGraph {
+    let contracts = api_set.map(index_contracts).unwrap_or_default();
+
+    let mut dependency_graph = DependencyGraph::default();
+    for (binary_name, binary_imports) in imports {
+        for (dll_name, dll_imports) in binary_imports {
+            let (host_name, contract_name) =
+                match resolve_contract(&contracts, binary_name, dll_name) {
+                    Some(host_name) => (host_name, Some(dll_name)),
+                    // Direct imports and unresolved contracts
+                    None => (dll_name.clone(), None),
+                };
+
+            let dependency = dependency_graph
+                .dependencies
+                .entry(binary_name.clone())
+                .or_default()
+                .entry(host_name.clone())
+                .or_default();
+            dependency
+                .functions
+                .extend(dll_imports.functions.iter().cloned());
+            dependency.contracts.extend(contract_name.cloned());
+            dependency.delay_loaded |= dll_imports.delay_loaded;
+
+            let host_importers = dependency_graph.importers.entry(host_name).or_default();
+            for function in &dll_imports.functions {
+                host_importers
+                    .entry(function.clone())
+                    .or_default()
+                    .insert(binary_name.clone());
+            }
+        }
+    }
+
+    dependency_graph
+}
+
+/// Index contracts by their name without minor version (e.g.,
+/// "api-ms-win-core-synch-l1-2"), which is the part the loader compares.
+fn index_contracts(api_set: &BTreeMap<String, ApiSetContract>) -> BTreeMap<&str, &ApiSetContract> {
+    api_set
+        .iter()
+        .map(|(contract_name, contract)| (strip_minor_version(contract_name), contract))
+        .collect()
+}
+
+/// Resolve an imported DLL to its host DLL if it's an API set contract
+fn resolve_contract(
+    contracts: &BTreeMap<&str, &ApiSetContract>,
+    importer_name: &str,
+    dll_name: &str,
+) -> Option<String> {
+    if !API_SET_CONTRACT_PREFIXES
+        .iter()
+        .any(|prefix| dll_name.starts_with(prefix))
+    {
+        return None;
+    }
+
+    let contract_name = dll_name.strip_suffix(".dll").unwrap_or(dll_name);
+    let contract = contracts.get(strip_minor_version(contract_name))?;
+    contract
+        .exceptions
+        .get(importer_name)
+        .or_else(|| contract.hosts.first())
+        .cloned()
+}
+
+fn strip_minor_version(contract_name: &str) -> &str {
+    contract_name
+        .rsplit_once('-')
+        .map(|(name, _)| name)
+        .unwrap_or(contract_name)
+}
@@ -45,4 +45,12 @@ pub enum WinDiffError {
     SystemServiceTableNotFoundError,
     #[error("failed to parse service table")]
     SystemServiceTableParsingError,
+    #[error("failed to parse hybrid metadata")]
+    HybridMetadataParsingError,
+    #[error("out-of-bounds read at offset {0:#x}")]
+    OutOfBoundsRead(usize),
+    #[error("unsupported API set schema version: {0}")]
+    UnsupportedApiSetSchemaVersion(u32),
+    #[error("failed to parse CLI metadata")]
+    CliMetadataParsingError,
 }
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    message_table::extract_message_tables,
+    pe_utils::{format_guid, read_bytes, read_u16, read_u32, read_u64, utf16_to_string},
+    resources::{find_resources, ResourceId},
+    tracelogging::in_type_name,
+};
+
+/// Name of the resource type that contains compiled instrumentation manifests
+const WEVT_TEMPLATE_RESOURCE_TYPE: &str = "WEVT_TEMPLATE";
+/// Language whose messages are preferred when resolving message identifiers
+const LANG_EN_US: u16 = 0x409;
+const NO_MESSAGE_ID: u32 = 0xffff_ffff;
+
+// Block signatures
+const CRIM_SIGNATURE: &[u8] = b"CRIM";
+const WEVT_SIGNATURE: &[u8] = b"WEVT";
+const CHAN_SIGNATURE: &[u8] = b"CHAN";
+const EVNT_SIGNATURE: &[u8] = b"EVNT";
+const KEYW_SIGNATURE: &[u8] = b"KEYW";
+const TEMP_SIGNATURE: &[u8] = b"TEMP";
+
+// Sizes of the block entries
+const SIZEOF_PROVIDER_DESCRIPTOR: usize = 20;
+const SIZEOF_ELEMENT_DESCRIPTOR: usize = 8;
+const SIZEOF_CHANNEL_DEFINITION: usize = 16;
+const SIZEOF_KEYWORD_DEFINITION: usize = 16;
+const SIZEOF_EVENT_DEFINITION: usize = 48;
+const SIZEOF_TEMPLATE_ITEM: usize = 20;
+
+/// Manifest-based ETW provider
+#[derive(Serialize, Debug, Default)]
+pub struct EtwManifestProvider {
+    pub name: Option<String>,
+    /// Channels (channel value -> channel name)
+    pub channels: BTreeMap<u8, String>,
+    /// Events ("identifier_vVersion" -> event)
+    pub events: BTreeMap<String, EtwManifestEvent>,
+}
+
+/// Event declared in an instrumentation manifest
+#[derive(Serialize, Debug)]
+pub struct EtwManifestEvent {
+    pub channel: String,
+    pub level: String,
+    pub task: String,
+    pub opcode: String,
+    pub keyword: String,
+    /// Names of the keywords set in `keyword`
+    pub keywords: Vec<String>,
+    pub message: Option<String>,
+    /// Template fields, formatted as "name : type"
+    pub fields: Vec<String>,
+}
+
+/// Extract the manifest-based ETW providers found in the WEVT_TEMPLATE
+/// resources of a given PE, as (provider GUID, provider) pairs.
+pub fn extract_etw_manifests(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+) -> Result<Vec<(String, EtwManifestProvider)>> {
+    let resources = find_resources(
+        pe,
+        pe_data,
+        &ResourceId::Name(WEVT_TEMPLATE_RESOURCE_TYPE.to_string()),
+    )?;
+    if resources.is_empty() {
+        return Ok(vec![]);
+    }
+    let messages = select_messages(extract_message_tables(pe, pe_data)?);
+
+    let mut result = vec![];
+    for resource in resources {
+        log::trace!(
+            "Parsing WEVT_TEMPLATE {:?} (language: {:#x})",
+            resource.name,
+            resource.language
+        );
+        match parse_crim(resource.data, &messages) {
+            Ok(providers) => result.extend(providers),
+            Err(err) => log::warn!("Failed to parse WEVT_TEMPLATE {:?}: {}", resource.name, err),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Keep the messages of a single language, preferably English
+fn select_messages(messages: Vec<(u16, u32, String)>) -> BTreeMap<u32, String> {
+    let language = messages
+        .iter()
+        .map(|(language, _, _)| *language)
+        .min_by_key(|language| (*language != LANG_EN_US, *language));
+
+    messages
+        .into_iter()
+        .filter(|(message_language, _, _)| Some(*message_language) == language)
+        .map(|(_, message_id, message)| (message_id, message))
+        .collect()
+}
+
+/// Parse a CRIM block (i.e., a compiled instrumentation manifest), which is
+/// the root of a WEVT_TEMPLATE resource. Offsets found in the manifest are
+/// relative to the beginning of this block.
+fn parse_crim(
+    data: &[u8],
+    messages: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, EtwManifestProvider)>> {
+    if read_bytes(data, 0, 4)? != CRIM_SIGNATURE {
+        log::debug!("Invalid CRIM signature");
+        return Ok(vec![]);
+    }
+    let provider_count = read_u32(data, 12)? as usize;
+
+    let mut result = vec![];
+    for provider_index in 0..provider_count {
+        let descriptor_offset = 16 + provider_index * SIZEOF_PROVIDER_DESCRIPTOR;
+        let guid = format_guid(read_bytes(data, descriptor_offset, 16)?.try_into()?);
+        let provider_offset = read_u32(data, descriptor_offset + 16)? as usize;
+        match parse_wevt(data, provider_offset, messages) {
+            Ok(provider) => result.push((guid, provider)),
+            Err(err) => log::warn!("Failed to parse provider {}: {}", guid, err),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a WEVT block (i.e., a provider)
+fn parse_wevt(
+    data: &[u8],
+    provider_offset: usize,
+    messages: &BTreeMap<u32, String>,
+) -> Result<EtwManifestProvider> {
+    let mut provider = EtwManifestProvider::default();
+    if read_bytes(data, provider_offset, 4)? != WEVT_SIGNATURE {
+        log::debug!("Invalid WEVT signature at offset {:#x}", provider_offset);
+        return Ok(provider);
+    }
+    provider.name = get_message(messages, read_u32(data, provider_offset + 8)?);
+    let element_count = read_u32(data, provider_offset + 12)? as usize;
+
+    // Elements are identified by their signature
+    let mut elements = BTreeMap::new();
+    for element_index in 0..element_count {
+        let element_offset = read_u32(
+            data,
+            provider_offset + 20 + element_index * SIZEOF_ELEMENT_DESCRIPTOR,
+        )? as usize;
+        elements.insert(read_bytes(data, element_offset, 4)?, element_offset);
+    }
+
+    if let Some(channels_offset) = elements.get(CHAN_SIGNATURE) {
+        provider.channels = parse_channels(data, *channels_offset)?;
+    }
+    let keywords = match elements.get(KEYW_SIGNATURE) {
+        Some(keywords_offset) => parse_keywords(data, *keywords_offset)?,
+        None => vec![],
+    };
+    if let Some(events_offset) = elements.get(EVNT_SIGNATURE) {
+        provider.events = parse_events(
+            data,
+            *events_offset,
+            &provider.channels,
+            &keywords,
+            messages,
+        )?;
+    }
+
+    Ok(provider)
+}
+
+/// Parse a CHAN block, as (channel value -> channel name) pairs
+fn parse_channels(data: &[u8], channels_offset: usize) -> Result<BTreeMap<u8, String>> {
+    let channel_count = read_u32(data, channels_offset + 8)? as usize;
+
+    let mut result = BTreeMap::new();
+    for channel_index in 0..channel_count {
+        let definition_offset = channels_offset + 12 + channel_index * SIZEOF_CHANNEL_DEFINITION;
+        let channel_value = read_u32(data, definition_offset)?;
+        let name_offset = read_u32(data, definition_offset + 4)? as usize;
+        result.insert(channel_value as u8, read_data_string(data, name_offset)?);
+    }
+
+    Ok(result)
+}
+
+/// Parse a KEYW block, as (keyword mask, keyword name) pairs
+fn parse_keywords(data: &[u8], keywords_offset: usize) -> Result<Vec<(u64, String)>> {
+    let keyword_count = read_u32(data, keywords_offset + 8)? as usize;
+
+    let mut result = Vec::with_capacity(keyword_count);
+    for keyword_index in 0..keyword_count {
+        let definition_offset = keywords_offset + 12 + keyword_index * SIZEOF_KEYWORD_DEFINITION;
+        let keyword_mask = read_u64(data, definition_offset)?;
+        let name_offset = read_u32(data, definition_offset + 12)? as usize;
+        result.push((keyword_mask, read_data_string(data, name_offset)?));
+    }
+
+    Ok(result)
+}
+
+/// Parse an EVNT block, as ("identifier_vVersion" -> event) pairs
+fn parse_events(
+    data: &[u8],
+    events_offset: usize,
+    channels: &BTreeMap<u8, String>,
+    keywords: &[(u64, String)],
+    messages: &BTreeMap<u32, String>,
+) -> Result<BTreeMap<String, EtwManifestEvent>> {
+    let event_count = read_u32(data, events_offset + 8)? as usize;
+
+    let mut result = BTreeMap::new();
+    for event_index in 0..event_count {
+        let definition_offset = events_offset + 16 + event_index * SIZEOF_EVENT_DEFINITION;
+        let definition = read_bytes(data, definition_offset, SIZEOF_EVENT_DEFINITION)?;
+        let event_id = read_u16(definition, 0)?;
+        let version = definition[2];
+        let channel = definition[3];
+        let level = definition[4];
+        let opcode = definition[5];
+        let task = read_u16(definition, 6)?;
+        let keyword = read_u64(definition, 8)?;
+        let message_id = read_u32(definition, 16)?;
+        let template_offset = read_u32(definition, 20)? as usize;
+        let opcode_offset = read_u32(definition, 24)? as usize;
+        let level_offset = read_u32(definition, 28)? as usize;
+        let task_offset = read_u32(definition, 32)? as usize;
+
+        let fields = if template_offset != 0 {
+            parse_template_fields(data, template_offset)?
+        } else {
+            vec![]
+        };
+        result.insert(
+            format!("{}_v{}", event_id, version),
+            EtwManifestEvent {
+                channel: channels
+                    .get(&channel)
+                    .cloned()
+                    .unwrap_or_else(|| channel.to_string()),
+                // Levels and opcodes start with their value and message
+                // identifier, tasks also have a GUID
+                level: read_definition_name(data, level_offset, 8)?
+                    .unwrap_or_else(|| level.to_string()),
+                task: read_definition_name(data, task_offset, 24)?
+                    .unwrap_or_else(|| task.to_string()),
+                opcode: read_definition_name(data, opcode_offset, 8)?
+                    .unwrap_or_else(|| opcode.to_string()),
+                keyword: format!("{:#x}", keyword),
+                keywords: keywords
+                    .iter()
+                    .filter(|(keyword_mask, _)| {
+                        *keyword_mask != 0 && keyword & keyword_mask == *keyword_mask
+                    })
+                    .map(|(_, keyword_name)| keyword_name.clone())
+                    .collect(),
+                message: get_message(messages, message_id),
+                fields,
+            },
+        );
+    }
+
+    Ok(result)
+}
+
+/// Parse the items of a TEMP block (i.e., an event template), formatted as
+/// "name : type"
+fn parse_template_fields(data: &[u8], template_offset: usize) -> Result<Vec<String>> {
+    if read_bytes(data, template_offset, 4)? != TEMP_SIGNATURE {
+        log::debug!("Invalid TEMP signature at offset {:#x}", template_offset);
+        return Ok(vec![]);
+    }
+    let item_count = read_u32(data, template_offset + 8)? as usize;
+    let items_offset = read_u32(data, template_offset + 16)? as usize;
+
+    let mut result = Vec::with_capacity(item_count);
+    for item_index in 0..item_count {
+        let item = read_bytes(
+            data,
+            items_offset + item_index * SIZEOF_TEMPLATE_ITEM,
+            SIZEOF_TEMPLATE_ITEM,
+        )?;
+        let in_type = item[4];
+        let name_offset = read_u32(item, 16)? as usize;
+        result.push(format!(
+            "{} : {}",
+            read_data_string(data, name_offset)?,
+            in_type_name(in_type)
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Read the name of a level, opcode or task definition whose name offset
+/// is located at `name_offset_position` in the definition. Returns `None` for
+/// null definition offsets.
+fn read_definition_name(
+    data: &[u8],
+    definition_offset: usize,
+    name_offset_position: usize,
+) -> Result<Option<String>> {
+    if definition_offset == 0 {
+        return Ok(None);
+    }
+    let name_offset = read_u32(data, definition_offset + name_offset_position)? as usize;
+
+    Ok(Some(read_data_string(data, name_offset)?))
+}
+
+/// Read a length-prefixed UTF-16 string (the length, in bytes, includes the
+/// length field itself)
+fn read_data_string(data: &[u8], offset: usize) -> Result<String> {
+    let size = read_u32(data, offset)? as usize;
+    let string_data = read_bytes(data, offset + 4, size.saturating_sub(4))?;
+
+    Ok(utf16_to_string(string_data)
+        .trim_end_matches('\0')
+        .to_string())
+}
+
+fn get_message(messages: &BTreeMap<u32, String>, message_id: u32) -> Option<String> {
+    if message_id == NO_MESSAGE_ID {
+        return None;
+    }
+    messages.get(&message_id).cloned()
+}
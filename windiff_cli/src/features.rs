@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    configuration::NameFilter,
+    error::Result,
+    pdb::Pdb,
+    pe_utils::{read_u32, rva_to_offset},
+};
+
+/// Prefix of WIL feature names
+const FEATURE_NAME_PREFIX: &str = "Feature_";
+/// Separator between a feature's name and the name of its generated helpers
+/// (e.g., "Feature_Foo__private_IsEnabledDeviceUsage")
+const PRIVATE_HELPER_SEPARATOR: &str = "__private_";
+/// Suffix of the symbols of feature descriptors
+const DESCRIPTOR_SUFFIX: &str = "__private_descriptor";
+/// Prefix of the traits types used by C++ helpers (e.g.,
+/// "wil::Feature<__WilFeatureTraits_Feature_Foo>::IsEnabled")
+const FEATURE_TRAITS_PREFIX: &str = "__WilFeatureTraits_";
+
+/// WIL staged feature
+#[derive(Serialize, Debug, Default)]
+pub struct Feature {
+    /// Velocity feature identifier (unknown if the feature's descriptor wasn't
+    /// found)
+    pub id: Option<u32>,
+    pub default_state: Option<FeatureStage>,
+}
+
+/// `wil_FeatureStage`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureStage {
+    AlwaysDisabled,
+    DisabledByDefault,
+    EnabledByDefault,
+    AlwaysEnabled,
+}
+
+/// Extract the WIL features referenced by a given PE's debug symbols, as
+/// (feature name, feature) pairs.
+pub fn extract_features(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    pdb: &mut Pdb,
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, Feature)>> {
+    let mut features: BTreeMap<String, Feature> = BTreeMap::new();
+    // Feature helpers are often folded together, so look at all the symbol
+    // names to find feature names
+    for symbol_name in pdb.extract_symbols(false, &NameFilter::default())? {
+        if let Some(feature_name) = parse_feature_name(&symbol_name) {
+            features.entry(feature_name.to_string()).or_default();
+        }
+    }
+    if features.is_empty() {
+        return Ok(vec![]);
+    }
+    log::trace!("Extracting WIL features");
+
+    // Descriptors
+    for (symbol_rva, symbol_name) in symbols {
+        // 32-bit binaries use decorated names
+        let feature_name = match symbol_name
+            .trim_start_matches('_')
+            .strip_suffix(DESCRIPTOR_SUFFIX)
+        {
+            Some(feature_name) if feature_name.starts_with(FEATURE_NAME_PREFIX) => feature_name,
+            _ => continue,
+        };
+
+        match parse_feature_descriptor(pe, pe_data, *symbol_rva) {
+            Ok(feature) => {
+                features.insert(feature_name.to_string(), feature);
+            }
+            Err(err) => log::warn!(
+                "Failed to parse descriptor of feature '{}': {}",
+                feature_name,
+                err
+            ),
+        }
+    }
+
+    Ok(features.into_iter().collect())
+}
+
+/// Extract a feature name from the name of one of its generated helpers
+fn parse_feature_name(symbol_name: &str) -> Option<&str> {
+    // C++ helpers
+    if let Some((_, traits_name)) = symbol_name.split_once(FEATURE_TRAITS_PREFIX) {
+        let name_length = traits_name
+            .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+            .unwrap_or(traits_name.len());
+        return Some(&traits_name[..name_length])
+            .filter(|feature_name| feature_name.starts_with(FEATURE_NAME_PREFIX));
+    }
+
+    // C helpers
+    symbol_name
+        .trim_start_matches('_')
+        .split_once(PRIVATE_HELPER_SEPARATOR)
+        .map(|(feature_name, _)| feature_name)
+        .filter(|feature_name| {
+            feature_name.len() > FEATURE_NAME_PREFIX.len()
+                && feature_name.starts_with(FEATURE_NAME_PREFIX)
+        })
+}
+
+/// Parse a `wil_details_FeatureDescriptor` structure, which starts with a
+/// pointer to the feature's cached state, followed by the feature's
+/// identifier and its stage.
+fn parse_feature_descriptor(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    descriptor_rva: u32,
+) -> Result<Feature> {
+    let pointer_size = if pe.is_64 { 8 } else { 4 };
+    let descriptor_offset = rva_to_offset(descriptor_rva as usize, pe)?;
+    let feature_id = read_u32(pe_data, descriptor_offset + pointer_size)?;
+    let feature_stage = read_u32(pe_data, descriptor_offset + pointer_size + 4)?;
+
+    Ok(Feature {
+        id: Some(feature_id),
+        default_state: match feature_stage {
+            0 => Some(FeatureStage::AlwaysDisabled),
+            1 => Some(FeatureStage::DisabledByDefault),
+            2 => Some(FeatureStage::EnabledByDefault),
+            3 => Some(FeatureStage::AlwaysEnabled),
+            _ => None,
+        },
+    })
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction};
+use serde::Serialize;
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    imports::{import_address_table, resolve_import_thunk},
+    pe_utils::{read_bytes, read_u32, read_u64, rva_to_offset},
+};
+
+const IMAGE_REL_BASED_HIGHLOW: u8 = 3;
+const IMAGE_REL_BASED_DIR64: u8 = 10;
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x100_0000_01b3;
+/// Name hashed for references that don't match any symbol
+const UNKNOWN_REFERENCE_NAME: &str = "?";
+
+/// Fingerprint of a function's code
+#[derive(Serialize, Debug)]
+pub struct FunctionHash {
+    /// FNV-1a hash of the function's normalized code
+    pub hash: String,
+    pub size: u32,
+    pub instruction_count: u32,
+}
+
+/// Hash the code of the given procedures (RVA -> (name, length)), as
+/// (function name, function hash) pairs. Addresses that depend on the
+/// function's location (relocations, RIP-relative displacements and branches
+/// that leave the function) are zeroed before hashing, and the names of the
+/// symbols or imports they reference are hashed instead, so that functions
+/// only get a different hash if their code or the functions they call
+/// changed.
+pub fn extract_function_hashes(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    procedures: &BTreeMap<u32, (String, u32)>,
+) -> Result<Vec<(String, FunctionHash)>> {
+    let bitness = if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+        Some(64)
+    } else if pe.header.coff_header.machine == OSArchitecture::I386.to_machine_type() {
+        Some(32)
+    } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+        None
+    } else {
+        log::debug!("Unsupported architecture, functions won't be hashed");
+        return Ok(vec![]);
+    };
+    log::trace!("Hashing functions");
+    let relocations = collect_relocations(pe);
+    let import_address_table = import_address_table(pe);
+    // IAT slots, symbols and import thunks
+    let symbolize_reference = |reference_rva: u32| {
+        match import_address_table.get(&reference_rva) {
+            Some(imported_function) => Some(imported_function.function.as_str()),
+            None => symbols.get(&reference_rva).map(String::as_str).or_else(|| {
+                resolve_import_thunk(pe, pe_data, &import_address_table, reference_rva)
+                    .map(|imported_function| imported_function.function.as_str())
+            }),
+        }
+        .unwrap_or(UNKNOWN_REFERENCE_NAME)
+    };
+
+    let mut result = Vec::with_capacity(procedures.len());
+    for (function_rva, (function_name, function_size)) in procedures {
+        if *function_size == 0 {
+            continue;
+        }
+        let function_code =
+            match rva_to_offset(*function_rva as usize, pe).and_then(|function_offset| {
+                read_bytes(pe_data, function_offset, *function_size as usize)
+            }) {
+                Ok(function_code) => function_code,
+                Err(err) => {
+                    log::debug!("Failed to read function '{}': {}", function_name, err);
+                    continue;
+                }
+            };
+
+        let mut normalized_code = function_code.to_vec();
+        // RVAs referenced by the function, in order
+        let mut references = vec![];
+        let function_end_rva = function_rva + function_size;
+        for (relocation_rva, relocation_size) in relocations.range(*function_rva..function_end_rva)
+        {
+            let start = (relocation_rva - function_rva) as usize;
+            let end = (start + *relocation_size as usize).min(normalized_code.len());
+            let address = match relocation_size {
+                8 => read_u64(function_code, start).ok(),
+                _ => read_u32(function_code, start).ok().map(u64::from),
+            };
+            references.extend(address.map(|address| address.wrapping_sub(pe.image_base) as u32));
+            normalized_code[start..end].fill(0);
+        }
+        let instruction_count = match bitness {
+            Some(bitness) => normalize_code_x86(
+                bitness,
+                pe.image_base,
+                *function_rva,
+                function_code,
+                &mut normalized_code,
+                &mut references,
+            ),
+            None => normalize_code_arm64(*function_rva, &mut normalized_code, &mut references),
+        };
+
+        let hash = references.into_iter().fold(
+            fnv1a(FNV_OFFSET_BASIS, &normalized_code),
+            |hash, reference_rva| {
+                // Separate names so that they can't be confused with each other
+                fnv1a(
+                    fnv1a(hash, symbolize_reference(reference_rva).as_bytes()),
+                    &[0],
+                )
+            },
+        );
+        result.push((
+            function_name.clone(),
+            FunctionHash {
+                hash: format!("{:016x}", hash),
+                size: *function_size,
+                instruction_count,
+            },
+        ));
+    }
+
+    Ok(result)
+}
+
+/// Collect the PE's base relocations, as (RVA, size) pairs. Parsing stops at
+/// the first malformed relocation, whose successors won't be normalized.
+fn collect_relocations(pe: &pe::PE<'_>) -> BTreeMap<u32, u32> {
+    let mut result = BTreeMap::new();
+    if let Some(relocation_data) = &pe.relocation_data {
+        for block in relocation_data.blocks() {
+            let block = match block {
+                Ok(block) => block,
+                Err(err) => {
+                    log::debug!("Stopping at malformed relocation block: {}", err);
+                    break;
+                }
+            };
+            for word in block.words() {
+                let word = match word {
+                    Ok(word) => word,
+                    Err(err) => {
+                        log::debug!("Stopping at malformed relocation: {}", err);
+                        return result;
+                    }
+                };
+                let relocation_size = match word.reloc_type() {
+                    IMAGE_REL_BASED_HIGHLOW => 4,
+                    IMAGE_REL_BASED_DIR64 => 8,
+                    _ => continue,
+                };
+                result.insert(block.rva + u32::from(word.offset()), relocation_size);
+            }
+        }
+    }
+
+    result
+}
+
+/// Zero the RIP-relative displacements and the displacements of branches that
+/// leave the function, record the RVAs they reference, and return the number
+/// of instructions
+fn normalize_code_x86(
+    bitness: u32,
+    image_base: u64,
+    function_rva: u32,
+    function_code: &[u8],
+    normalized_code: &mut [u8],
+    references: &mut Vec<u32>,
+) -> u32 {
+    let function_address = image_base + u64::from(function_rva);
+    let function_range = function_address..function_address + function_code.len() as u64;
+
+    let mut decoder = Decoder::with_ip(
+        bitness,
+        function_code,
+        function_address,
+        DecoderOptions::NONE,
+    );
+    let mut instruction = Instruction::default();
+    let mut instruction_count = 0;
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        instruction_count += 1;
+        let instruction_offset = (instruction.ip() - function_address) as usize;
+        let constant_offsets = decoder.get_constant_offsets(&instruction);
+
+        if instruction.is_ip_rel_memory_operand() {
+            let start = instruction_offset + constant_offsets.displacement_offset();
+            normalized_code[start..start + constant_offsets.displacement_size()].fill(0);
+            references.push(instruction.ip_rel_memory_address().wrapping_sub(image_base) as u32);
+        }
+        if matches!(
+            instruction.flow_control(),
+            FlowControl::Call | FlowControl::UnconditionalBranch | FlowControl::ConditionalBranch
+        ) && !function_range.contains(&instruction.near_branch_target())
+        {
+            // Branch displacements are reported as immediates
+            let start = instruction_offset + constant_offsets.immediate_offset();
+            normalized_code[start..start + constant_offsets.immediate_size()].fill(0);
+            references.push(instruction.near_branch_target().wrapping_sub(image_base) as u32);
+        }
+    }
+
+    instruction_count
+}
+
+/// Zero the PC-relative immediates of instructions that reference data or
+/// code outside of the function, record the RVAs they reference, and return
+/// the number of instructions
+fn normalize_code_arm64(
+    function_rva: u32,
+    normalized_code: &mut [u8],
+    references: &mut Vec<u32>,
+) -> u32 {
+    let function_size = normalized_code.len() as i64;
+    // Page addresses computed with ADRP, by register
+    let mut page_registers: [Option<u32>; 32] = [None; 32];
+
+    let mut instruction_count = 0;
+    for (i, instruction_bytes) in normalized_code.chunks_exact_mut(4).enumerate() {
+        instruction_count += 1;
+        let instruction_offset = 4 * i as i64;
+        let instruction_rva = function_rva + instruction_offset as u32;
+        let mut instruction = u32::from_le_bytes([
+            instruction_bytes[0],
+            instruction_bytes[1],
+            instruction_bytes[2],
+            instruction_bytes[3],
+        ]);
+        let rd = (instruction & 0x1f) as usize;
+        let rn = ((instruction >> 5) & 0x1f) as usize;
+        let is_outside_function =
+            |displacement: i64| !(0..function_size).contains(&(instruction_offset + displacement));
+        let target_rva = |displacement: i64| instruction_rva.wrapping_add(displacement as u32);
+
+        // B and BL
+        if instruction & 0x7c00_0000 == 0x1400_0000 {
+            // Sign-extend the 26-bit immediate
+            let displacement = (((instruction & 0x3ff_ffff) << 6) as i32 >> 4) as i64;
+            if is_outside_function(displacement) {
+                instruction &= !0x3ff_ffff;
+                references.push(target_rva(displacement));
+            }
+        }
+        // B.cond, CBZ and CBNZ
+        else if instruction & 0xff00_0010 == 0x5400_0000
+            || instruction & 0x7e00_0000 == 0x3400_0000
+        {
+            // Sign-extend the 19-bit immediate
+            let displacement = ((((instruction >> 5) & 0x7_ffff) << 13) as i32 >> 11) as i64;
+            if is_outside_function(displacement) {
+                instruction &= !(0x7_ffff << 5);
+                references.push(target_rva(displacement));
+            }
+        }
+        // TBZ and TBNZ
+        else if instruction & 0x7e00_0000 == 0x3600_0000 {
+            // Sign-extend the 14-bit immediate
+            let displacement = ((((instruction >> 5) & 0x3fff) << 18) as i32 >> 16) as i64;
+            if is_outside_function(displacement) {
+                instruction &= !(0x3fff << 5);
+                references.push(target_rva(displacement));
+            }
+        }
+        // ADR and ADRP
+        else if instruction & 0x1f00_0000 == 0x1000_0000 {
+            let immlo = (instruction >> 29) & 0x3;
+            let immhi = (instruction >> 5) & 0x7_ffff;
+            // Sign-extend the 21-bit immediate
+            let imm = ((((immhi << 2) | immlo) << 11) as i32 >> 11) as i64;
+            if instruction & 0x8000_0000 != 0 {
+                page_registers[rd] =
+                    Some((instruction_rva & !0xfff).wrapping_add((imm << 12) as u32));
+            } else {
+                references.push(target_rva(imm));
+                page_registers[rd] = None;
+            }
+            instruction &= !(0x6000_0000 | (0x7_ffff << 5));
+        }
+        // LDR (literal)
+        else if instruction & 0x3b00_0000 == 0x1800_0000 {
+            // Sign-extend the 19-bit immediate
+            let displacement = ((((instruction >> 5) & 0x7_ffff) << 13) as i32 >> 11) as i64;
+            references.push(target_rva(displacement));
+            instruction &= !(0x7_ffff << 5);
+            page_registers[rd] = None;
+        }
+        // ADD (immediate) and loads/stores (unsigned offset) relative to a
+        // page address
+        else if let (true, Some(page_address)) = (
+            instruction & 0x7f80_0000 == 0x1100_0000 || instruction & 0x3b00_0000 == 0x3900_0000,
+            page_registers[rn],
+        ) {
+            let imm12 = (instruction >> 10) & 0xfff;
+            let page_offset = if instruction & 0x3b00_0000 == 0x3900_0000 {
+                // Scaled by the access size
+                imm12 << (instruction >> 30)
+            } else {
+                imm12 << (12 * ((instruction >> 22) & 1))
+            };
+            references.push(page_address.wrapping_add(page_offset));
+            instruction &= !(0xfff << 10);
+            page_registers[rd] = None;
+        }
+        // Consider that other instructions overwrite their first operand
+        else {
+            page_registers[rd] = None;
+        }
+
+        instruction_bytes.copy_from_slice(&instruction.to_le_bytes());
+    }
+
+    instruction_count
+}
+
+/// 64-bit FNV-1a hash, continued from `hash`
+fn fnv1a(hash: u64, data: &[u8]) -> u64 {
+    data.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+    })
+}
@@ -0,0 +1,231 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use iced_x86::{Decoder, DecoderOptions, FlowControl};
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    pe_utils::{read_c_string, read_u32, read_u64, rva_to_offset},
+};
+
+/// Maximum number of instructions a thunk can be made of
+const MAX_THUNK_INSTRUCTIONS: usize = 6;
+/// Size of an `IMAGE_DELAYLOAD_DESCRIPTOR`
+const DELAYLOAD_DESCRIPTOR_SIZE: usize = 32;
+/// `IMAGE_DELAYLOAD_DESCRIPTOR.Attributes.RvaBased`, cleared by old linkers
+/// that stored VAs instead of RVAs
+const DELAYLOAD_ATTRIBUTE_RVA_BASED: u32 = 0x1;
+/// Upper bounds used to stop walking malformed tables
+const MAX_DELAYLOAD_DESCRIPTOR_COUNT: usize = 0x1000;
+const MAX_DELAYLOAD_IMPORT_COUNT: usize = 0x10000;
+
+/// Imported function, identified by its module and function names
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedFunction {
+    pub module: String,
+    pub function: String,
+}
+
+/// Map the RVAs of a PE's import address table slots to the function they
+/// import
+pub fn import_address_table(pe: &pe::PE<'_>) -> BTreeMap<u32, ImportedFunction> {
+    pe.imports
+        .iter()
+        // Note: `offset` contains the RVA of the IAT slot
+        .map(|import| {
+            (
+                import.offset as u32,
+                ImportedFunction {
+                    module: import.dll.to_string(),
+                    function: import.name.to_string(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// List the functions imported through the delay-load import directory, which
+/// goblin doesn't parse. Functions imported by ordinal are named like goblin
+/// does (e.g., "ORDINAL 12").
+pub fn delay_load_imports(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<ImportedFunction>> {
+    let delay_import_directory = match pe.header.optional_header.and_then(|optional_header| {
+        optional_header
+            .data_directories
+            .get_delay_import_descriptor()
+            .copied()
+    }) {
+        Some(delay_import_directory) if delay_import_directory.virtual_address != 0 => {
+            delay_import_directory
+        }
+        _ => return Ok(vec![]),
+    };
+    let descriptors_offset = rva_to_offset(delay_import_directory.virtual_address as usize, pe)?;
+
+    let mut result = vec![];
+    for descriptor_index in 0..MAX_DELAYLOAD_DESCRIPTOR_COUNT {
+        let descriptor_offset = descriptors_offset + descriptor_index * DELAYLOAD_DESCRIPTOR_SIZE;
+        let attributes = read_u32(pe_data, descriptor_offset)?;
+        let dll_name_rva = read_u32(pe_data, descriptor_offset + 4)?;
+        let name_table_rva = read_u32(pe_data, descriptor_offset + 16)?;
+        if dll_name_rva == 0 {
+            break;
+        }
+        // Old descriptors contain VAs
+        let to_rva = |address: u64| -> u32 {
+            if attributes & DELAYLOAD_ATTRIBUTE_RVA_BASED != 0 {
+                address as u32
+            } else {
+                address.wrapping_sub(pe.image_base) as u32
+            }
+        };
+
+        let dll_name = read_c_string(
+            pe_data,
+            rva_to_offset(to_rva(u64::from(dll_name_rva)) as usize, pe)?,
+        )?;
+        let name_table_offset = rva_to_offset(to_rva(u64::from(name_table_rva)) as usize, pe)?;
+        for import_index in 0..MAX_DELAYLOAD_IMPORT_COUNT {
+            // IMAGE_THUNK_DATA
+            let (thunk, is_ordinal) = if pe.is_64 {
+                let thunk = read_u64(pe_data, name_table_offset + import_index * 8)?;
+                (thunk, thunk & (1 << 63) != 0)
+            } else {
+                let thunk = read_u32(pe_data, name_table_offset + import_index * 4)?;
+                (u64::from(thunk), thunk & (1 << 31) != 0)
+            };
+            if thunk == 0 {
+                break;
+            }
+
+            let function = if is_ordinal {
+                format!("ORDINAL {}", thunk & 0xffff)
+            } else {
+                // IMAGE_IMPORT_BY_NAME, which starts with a 16-bit hint
+                read_c_string(pe_data, rva_to_offset(to_rva(thunk) as usize, pe)? + 2)?
+            };
+            result.push(ImportedFunction {
+                module: dll_name.clone(),
+                function,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Determine if the function located at the given RVA is a thunk that jumps
+/// to an imported function, and return that function if it is.
+pub fn resolve_import_thunk<'i>(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    import_address_table: &'i BTreeMap<u32, ImportedFunction>,
+    rva: u32,
+) -> Option<&'i ImportedFunction> {
+    let offset = rva_to_offset(rva as usize, pe).ok()?;
+    let code = pe_data.get(offset..)?;
+
+    let iat_slot_rva = if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+        find_thunk_iat_slot_amd64(import_address_table, pe.image_base, rva, code)
+    } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+        find_thunk_iat_slot_arm64(import_address_table, rva, code)
+    } else {
+        // Not supported
+        None
+    }?;
+
+    import_address_table.get(&iat_slot_rva)
+}
+
+/// Find the IAT slot referenced by an AMD64 thunk. We want to match stubs such
+/// as:
+/// jmp qword ptr [rip+IAT_SLOT]
+/// or:
+/// mov rax, qword ptr [rip+IAT_SLOT]
+/// jmp rax ; or a call to the CFG dispatch routine
+/// ...
+/// ret
+fn find_thunk_iat_slot_amd64(
+    import_address_table: &BTreeMap<u32, ImportedFunction>,
+    image_base: u64,
+    rva: u32,
+    code: &[u8],
+) -> Option<u32> {
+    let mut decoder = Decoder::with_ip(64, code, image_base + rva as u64, DecoderOptions::NONE);
+
+    let mut iat_slot_rva = None;
+    for instruction in decoder.iter().take(MAX_THUNK_INSTRUCTIONS) {
+        if instruction.is_invalid() {
+            return None;
+        }
+        if iat_slot_rva.is_none() && instruction.is_ip_rel_memory_operand() {
+            let memory_rva = instruction.ip_rel_memory_address().wrapping_sub(image_base) as u32;
+            if import_address_table.contains_key(&memory_rva) {
+                iat_slot_rva = Some(memory_rva);
+            }
+        }
+
+        // Stop at the end of the thunk
+        if matches!(
+            instruction.flow_control(),
+            FlowControl::Return | FlowControl::IndirectBranch | FlowControl::UnconditionalBranch
+        ) {
+            return iat_slot_rva;
+        }
+    }
+
+    None
+}
+
+/// Find the IAT slot referenced by an ARM64 thunk. We want to match stubs such
+/// as:
+/// ADRP Xn, IAT_SLOT@PAGE
+/// LDR Xn, [Xn, IAT_SLOT@PAGEOFF]
+/// BR Xn ; or BLR, followed by a RET
+fn find_thunk_iat_slot_arm64(
+    import_address_table: &BTreeMap<u32, ImportedFunction>,
+    rva: u32,
+    code: &[u8],
+) -> Option<u32> {
+    // Values loaded into registers with ADRP
+    let mut page_addresses: [Option<u32>; 32] = [None; 32];
+
+    let mut iat_slot_rva = None;
+    for (i, instruction_bytes) in code
+        .chunks_exact(4)
+        .take(MAX_THUNK_INSTRUCTIONS)
+        .enumerate()
+    {
+        let instruction_rva = rva + 4 * i as u32;
+        let instruction = u32::from_le_bytes(instruction_bytes.try_into().ok()?);
+
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/ADRP--Form-PC-relative-address-to-4KB-page-
+        if instruction & 0x9f00_0000 == 0x9000_0000 {
+            let rd = (instruction & 0x1f) as usize;
+            let immlo = (instruction >> 29) & 0x3;
+            let immhi = (instruction >> 5) & 0x7_ffff;
+            // Sign-extend the 21-bit immediate and convert it to a page offset
+            let imm = (((immhi << 2) | immlo) << 11) as i32 >> 11;
+            page_addresses[rd] = Some((instruction_rva & !0xfff).wrapping_add((imm << 12) as u32));
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/LDR--immediate---Load-Register--immediate--
+        else if instruction & 0xffc0_0000 == 0xf940_0000 {
+            let rn = ((instruction >> 5) & 0x1f) as usize;
+            let imm12 = (instruction >> 10) & 0xfff;
+            if let (None, Some(page_address)) = (iat_slot_rva, page_addresses[rn]) {
+                let memory_rva = page_address.wrapping_add(imm12 * 8);
+                if import_address_table.contains_key(&memory_rva) {
+                    iat_slot_rva = Some(memory_rva);
+                }
+            }
+        }
+        // BR Xn and RET
+        else if instruction & 0xffff_fc1f == 0xd61f_0000
+            || instruction & 0xffff_fc1f == 0xd65f_0000
+        {
+            return iat_slot_rva;
+        }
+    }
+
+    None
+}
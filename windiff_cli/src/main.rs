@@ -1,12 +1,36 @@
+mod apiset;
+mod arm64x;
+mod call_graph;
+mod callbacks;
 mod cli;
+mod cli_metadata;
 mod configuration;
 mod database;
+mod dependency_graph;
 mod download;
 mod error;
+mod etw_manifest;
+mod features;
+mod function_hashes;
+mod imports;
+mod message_table;
 mod pdb;
+mod pe_utils;
+mod pool_tags;
+mod resources;
 mod resym_frontend;
+mod rpc;
+mod rtti;
+mod strings;
+mod synthesized_symbols;
+mod syscall_interface;
 mod syscalls;
+mod tls_exceptions;
+mod tracelogging;
+mod typelib;
+mod vtables;
 mod winbindex;
+mod wnf;
 
 use database::generate_database_index;
 use env_logger::Env;
@@ -16,8 +40,10 @@ use crate::{
     cli::WinDiffOpt,
     configuration::WinDiffConfiguration,
     database::{generate_databases, BinariesWithInfo},
+    dependency_graph::BinaryDependencies,
     download::{download_all_binaries, download_all_pdbs, download_single_binary},
     error::Result,
+    syscall_interface::generate_syscall_interfaces,
 };
 
 const PACKAGE_NAME: &str = env!("CARGO_PKG_NAME");
@@ -46,6 +72,7 @@ async fn main() -> Result<()> {
 async fn low_storage_mode(opt: WinDiffOpt, cfg: WinDiffConfiguration) -> Result<()> {
     let mut download_binaries_acc = vec![];
     let mut binaries_with_info_acc = BinariesWithInfo::default();
+    let mut binary_dependencies_acc = BinaryDependencies::default();
     for pe_name in cfg.binaries.keys() {
         let tmp_directory = tempfile::Builder::new().prefix(PACKAGE_NAME).tempdir()?;
         let tmp_directory_path = tmp_directory.path();
@@ -67,6 +94,8 @@ async fn low_storage_mode(opt: WinDiffOpt, cfg: WinDiffConfiguration) -> Result<
 
         // Merge the per-binary information maps into the global one
         binaries_with_info_acc.merge(binaries_with_info);
+        // Collect imports while PEs are still available on disk
+        binary_dependencies_acc.merge(BinaryDependencies::collect(&downloaded_binaries).await);
 
         // Move binary info into the global vec
         download_binaries_acc.append(&mut downloaded_binaries);
@@ -79,6 +108,12 @@ async fn low_storage_mode(opt: WinDiffOpt, cfg: WinDiffConfiguration) -> Result<
         &opt.output_directory,
     )
     .await?;
+    // Join syscall databases into per-OS syscall interfaces
+    generate_syscall_interfaces(&download_binaries_acc, &opt.output_directory).await?;
+    // Resolve binaries' imports into per-OS dependency graphs
+    binary_dependencies_acc
+        .write_dependency_graphs(&opt.output_directory)
+        .await?;
     log::info!(
         "Databases have been generated at {:?}",
         opt.output_directory
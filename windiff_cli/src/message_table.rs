@@ -0,0 +1,79 @@
+use goblin::pe;
+
+use crate::{
+    error::Result,
+    pe_utils::{read_bytes, read_u16, read_u32, utf16_to_string},
+    resources::{find_resources, ResourceId},
+};
+
+const MESSAGE_RESOURCE_UNICODE: u16 = 0x1;
+/// Upper bound used to reject malformed message blocks
+const MAX_MESSAGE_BLOCK_SIZE: u32 = 0x10000;
+
+/// Extract the messages found in the message tables (RT_MESSAGETABLE
+/// resources) of a given PE, as (language, message identifier, message text)
+/// tuples.
+pub fn extract_message_tables(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(u16, u32, String)>> {
+    let resources = find_resources(pe, pe_data, &ResourceId::Id(pe::resource::RT_MESSAGETABLE))?;
+
+    let mut result = vec![];
+    for resource in resources {
+        log::trace!(
+            "Parsing message table {:?} (language: {:#x})",
+            resource.name,
+            resource.language
+        );
+        match parse_message_table(resource.data) {
+            Ok(messages) => result.extend(
+                messages
+                    .into_iter()
+                    .map(|(message_id, message)| (resource.language, message_id, message)),
+            ),
+            Err(err) => log::warn!("Failed to parse message table {:?}: {}", resource.name, err),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a `MESSAGE_RESOURCE_DATA` structure
+fn parse_message_table(data: &[u8]) -> Result<Vec<(u32, String)>> {
+    let block_count = read_u32(data, 0)? as usize;
+
+    let mut result = vec![];
+    for block_index in 0..block_count {
+        // MESSAGE_RESOURCE_BLOCK
+        let block_offset = 4 + block_index * 12;
+        let low_id = read_u32(data, block_offset)?;
+        let high_id = read_u32(data, block_offset + 4)?;
+        let mut entry_offset = read_u32(data, block_offset + 8)? as usize;
+        if high_id < low_id || high_id - low_id >= MAX_MESSAGE_BLOCK_SIZE {
+            continue;
+        }
+
+        for message_id in low_id..=high_id {
+            // MESSAGE_RESOURCE_ENTRY
+            let entry_length = read_u16(data, entry_offset)? as usize;
+            let entry_flags = read_u16(data, entry_offset + 2)?;
+            if entry_length < 4 {
+                break;
+            }
+            let text = read_bytes(data, entry_offset + 4, entry_length - 4)?;
+            let text = if entry_flags & MESSAGE_RESOURCE_UNICODE != 0 {
+                utf16_to_string(text)
+            } else {
+                String::from_utf8_lossy(text).to_string()
+            };
+            // Messages are padded with NUL characters and usually end with a
+            // line break
+            result.push((
+                message_id,
+                text.trim_end_matches(['\0', '\r', '\n']).to_string(),
+            ));
+
+            entry_offset += entry_length;
+        }
+    }
+
+    Ok(result)
+}
@@ -9,7 +9,10 @@ use goblin::{pe::debug::DebugData, Object};
 use pdb::FallibleIterator;
 use tokio::{fs::File, io::AsyncReadExt};
 
-use crate::error::{Result, WinDiffError};
+use crate::{
+    configuration::NameFilter,
+    error::{Result, WinDiffError},
+};
 
 const MSDL_FILE_DOWNLOAD_BASE_URL: &str = "https://msdl.microsoft.com/download/symbols/";
 
@@ -26,14 +29,23 @@ impl<'p> Pdb<'p> {
         Ok(Self { file_path, pdb })
     }
 
-    pub fn extract_symbols(&mut self, differentiate_functions: bool) -> Result<BTreeSet<String>> {
+    /// Extract the names of the symbols that match the given filter.
+    pub fn extract_symbols(
+        &mut self,
+        differentiate_functions: bool,
+        filter: &NameFilter,
+    ) -> Result<BTreeSet<String>> {
         log::trace!("Extracting symbols from {:?}", self.file_path);
 
         let mut symbols = Vec::new();
 
         // Global symbols
         let symbol_table = self.pdb.global_symbols()?;
-        symbols.append(&mut self.walk_symbols(symbol_table.iter(), differentiate_functions)?);
+        symbols.append(&mut self.walk_symbols(
+            symbol_table.iter(),
+            differentiate_functions,
+            filter,
+        )?);
 
         // Modules' private symbols
         let dbi = self.pdb.debug_information()?;
@@ -46,7 +58,11 @@ impl<'p> Pdb<'p> {
                 }
             };
 
-            symbols.append(&mut self.walk_symbols(info.symbols()?, differentiate_functions)?);
+            symbols.append(&mut self.walk_symbols(
+                info.symbols()?,
+                differentiate_functions,
+                filter,
+            )?);
         }
 
         Ok(symbols.into_iter().collect())
@@ -100,15 +116,83 @@ impl<'p> Pdb<'p> {
         Ok(result)
     }
 
+    /// Extract the functions known to the PDB, as (RVA, (name, length))
+    /// pairs. Public PDBs only contain public symbols, which don't have a
+    /// length, so lengths are only known for private procedures.
+    pub fn extract_functions(&mut self) -> Result<BTreeMap<u32, (String, Option<u32>)>> {
+        log::trace!("Extracting functions from {:?}", self.file_path);
+
+        let addr_map = self.pdb.address_map()?;
+        // Private procedures, if any
+        let mut result: BTreeMap<u32, (String, Option<u32>)> = self
+            .extract_procedures()?
+            .into_iter()
+            .map(|(rva, (name, length))| (rva, (name, Some(length))))
+            .collect();
+
+        // Function publics
+        let symbol_table = self.pdb.global_symbols()?;
+        let mut symbols = symbol_table.iter();
+        while let Some(symbol) = symbols.next()? {
+            if let Ok(pdb::SymbolData::Public(data)) = symbol.parse() {
+                if let (true, Some(rva)) = (data.function, data.offset.to_rva(&addr_map)) {
+                    result
+                        .entry(rva.0)
+                        .or_insert((data.name.to_string().to_string(), None));
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Extract the procedures defined in the PDB, as (RVA, (name, length))
+    /// pairs
+    fn extract_procedures(&mut self) -> Result<BTreeMap<u32, (String, u32)>> {
+        log::trace!("Extracting procedures from {:?}", self.file_path);
+
+        let addr_map = self.pdb.address_map()?;
+        let mut result = BTreeMap::new();
+
+        // Modules' private symbols
+        let dbi = self.pdb.debug_information()?;
+        let mut modules = dbi.modules()?;
+        while let Some(module) = modules.next()? {
+            let info = match self.pdb.module_info(&module)? {
+                Some(info) => info,
+                None => {
+                    continue;
+                }
+            };
+
+            let mut symbols = info.symbols()?;
+            while let Some(symbol) = symbols.next()? {
+                if let Ok(pdb::SymbolData::Procedure(data)) = symbol.parse() {
+                    if let Some(rva) = data.offset.to_rva(&addr_map) {
+                        result
+                            .entry(rva.0)
+                            .or_insert((data.name.to_string().to_string(), data.len));
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
     fn walk_symbols(
         &mut self,
         mut symbols: pdb::SymbolIter<'_>,
         differentiate_functions: bool,
+        filter: &NameFilter,
     ) -> Result<Vec<String>> {
         let mut result = Vec::new();
         while let Some(symbol) = symbols.next()? {
             if let Ok(value) = self.dump_symbol(&symbol, differentiate_functions) {
-                result.push(value);
+                // Ignore the parentheses that distinguish functions
+                if filter.is_match(value.strip_suffix("()").unwrap_or(&value)) {
+                    result.push(value);
+                }
             }
         }
 
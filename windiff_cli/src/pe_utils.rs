@@ -0,0 +1,225 @@
+use std::collections::BTreeMap;
+
+use goblin::pe::{
+    self,
+    exception::{ARM64_PDATA_PACKED_UNWIND_FRAGMENT, ARM64_PDATA_REF_TO_FULL_XDATA},
+};
+
+use crate::{
+    configuration::OSArchitecture,
+    error::{Result, WinDiffError},
+};
+
+/// Convert an RVA to a file offset
+pub fn rva_to_offset(rva: usize, pe: &pe::PE<'_>) -> Result<usize> {
+    let mut parse_options = pe::options::ParseOptions::default();
+    parse_options.resolve_rva = true;
+    pe::utils::find_offset(
+        rva,
+        &pe.sections,
+        pe.header
+            .optional_header
+            .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)?
+            .windows_fields
+            .file_alignment,
+        &parse_options,
+    )
+    .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)
+}
+
+/// Generate a name for an RVA that doesn't match any symbol
+pub fn symbolize_unknown_rva(pe: &pe::PE<'_>, symbols: &BTreeMap<u32, String>, rva: u32) -> String {
+    // Exports
+    if let Some(export_name) = pe
+        .exports
+        .iter()
+        .find(|export| export.rva == rva as usize)
+        .and_then(|export| export.name)
+    {
+        return export_name.to_string();
+    }
+    // Nearest preceding symbol
+    if let Some((symbol_rva, symbol_name)) = symbols.range(..rva).next_back() {
+        if *symbol_rva != 0 {
+            return format!("{}+{:#x}", symbol_name, rva - symbol_rva);
+        }
+    }
+
+    format!("sub_{:x}", rva)
+}
+
+/// Determine if the given RVA is located in an executable section
+pub fn is_code_rva(pe: &pe::PE<'_>, rva: u32) -> bool {
+    pe.sections.iter().any(|section| {
+        section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE != 0
+            && rva >= section.virtual_address
+            && rva - section.virtual_address < section.virtual_size.max(section.size_of_raw_data)
+    })
+}
+
+/// Format a GUID stored in its binary form (e.g., "8a885d04-1ceb-11c9-9fe8-08002b104860")
+pub fn format_guid(guid: &[u8; 16]) -> String {
+    format!(
+        "{:08x}-{:04x}-{:04x}-{}-{}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]]),
+        hex::encode(&guid[8..10]),
+        hex::encode(&guid[10..])
+    )
+}
+
+/// Read a little-endian `u16` at the given file offset
+pub fn read_u16(data: &[u8], offset: usize) -> Result<u16> {
+    Ok(u16::from_le_bytes(
+        read_bytes(data, offset, std::mem::size_of::<u16>())?.try_into()?,
+    ))
+}
+
+/// Read a little-endian `u32` at the given file offset
+pub fn read_u32(data: &[u8], offset: usize) -> Result<u32> {
+    Ok(u32::from_le_bytes(
+        read_bytes(data, offset, std::mem::size_of::<u32>())?.try_into()?,
+    ))
+}
+
+/// Read a little-endian `u64` at the given file offset
+pub fn read_u64(data: &[u8], offset: usize) -> Result<u64> {
+    Ok(u64::from_le_bytes(
+        read_bytes(data, offset, std::mem::size_of::<u64>())?.try_into()?,
+    ))
+}
+
+/// Read a pointer-sized value at the given file offset and convert it to an RVA.
+/// Returns `None` for null pointers and pointers outside of the image.
+pub fn read_pointer_as_rva(pe: &pe::PE<'_>, data: &[u8], offset: usize) -> Result<Option<u32>> {
+    let va = if pe.is_64 {
+        read_u64(data, offset)?
+    } else {
+        u64::from(read_u32(data, offset)?)
+    };
+
+    let size_of_image = pe
+        .header
+        .optional_header
+        .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)?
+        .windows_fields
+        .size_of_image;
+
+    Ok(va
+        .checked_sub(pe.image_base)
+        .filter(|rva| va != 0 && *rva < u64::from(size_of_image))
+        .map(|rva| rva as u32))
+}
+
+/// Borrow `size` bytes at the given file offset
+pub fn read_bytes(data: &[u8], offset: usize, size: usize) -> Result<&[u8]> {
+    data.get(offset..offset.saturating_add(size))
+        .ok_or(WinDiffError::OutOfBoundsRead(offset))
+}
+
+/// Decode a little-endian UTF-16 string
+pub fn utf16_to_string(data: &[u8]) -> String {
+    let string_utf16: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect();
+
+    String::from_utf16_lossy(&string_utf16)
+}
+
+/// Read a NUL-terminated ASCII string at the given file offset
+pub fn read_c_string(data: &[u8], offset: usize) -> Result<String> {
+    let string_data = data
+        .get(offset..)
+        .ok_or(WinDiffError::OutOfBoundsRead(offset))?;
+    let string_len = string_data
+        .iter()
+        .position(|c| *c == 0)
+        .ok_or(WinDiffError::OutOfBoundsRead(offset))?;
+
+    Ok(std::str::from_utf8(&string_data[..string_len])?.to_string())
+}
+
+/// Get the functions described by the exception directory, as (RVA, length)
+/// pairs. Entries that describe a fragment of another function are ignored.
+pub fn runtime_functions(pe: &pe::PE<'_>) -> Result<BTreeMap<u32, u32>> {
+    let exception_data = match &pe.exception_data {
+        Some(exception_data) => exception_data,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    let mut result = BTreeMap::new();
+    if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+        for function in exception_data.functions() {
+            let function = function?;
+            let is_chained = exception_data
+                .get_unwind_info(function, &pe.sections)
+                .map(|unwind_info| unwind_info.chained_info.is_some())
+                .unwrap_or_default();
+            if !is_chained {
+                result.insert(
+                    function.begin_address,
+                    function.end_address.saturating_sub(function.begin_address),
+                );
+            }
+        }
+    } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+        for function in exception_data.functions_arm64() {
+            let function = function?;
+            let function_size = match function.flag() {
+                ARM64_PDATA_PACKED_UNWIND_FRAGMENT => continue,
+                ARM64_PDATA_REF_TO_FULL_XDATA => {
+                    match exception_data.get_unwind_info_arm64(function, &pe.sections) {
+                        Some(Ok(unwind_info)) => unwind_info.header.function_length(),
+                        _ => continue,
+                    }
+                }
+                _ => function.function_length(),
+            };
+            result.insert(function.begin_address, function_size);
+        }
+    }
+
+    Ok(result)
+}
+
+/// Determine the length of the given functions (RVA -> (name, length)) whose
+/// length isn't known. Lengths are taken from the exception directory if
+/// possible, or functions are assumed to end where the next function (or
+/// their section) starts otherwise.
+pub fn delimit_functions(
+    pe: &pe::PE<'_>,
+    functions: BTreeMap<u32, (String, Option<u32>)>,
+) -> BTreeMap<u32, (String, u32)> {
+    let runtime_functions = runtime_functions(pe).unwrap_or_else(|err| {
+        log::debug!("Failed to parse exception directory: {}", err);
+        BTreeMap::new()
+    });
+
+    let mut result = BTreeMap::new();
+    let mut functions = functions.into_iter().peekable();
+    while let Some((function_rva, (function_name, function_size))) = functions.next() {
+        let function_size = function_size
+            .or_else(|| runtime_functions.get(&function_rva).copied())
+            .unwrap_or_else(|| {
+                let section_end = pe
+                    .sections
+                    .iter()
+                    .find(|section| {
+                        function_rva >= section.virtual_address
+                            && function_rva - section.virtual_address < section.virtual_size
+                    })
+                    .map(|section| section.virtual_address + section.virtual_size)
+                    .unwrap_or(function_rva);
+                let function_end = match functions.peek() {
+                    Some((next_function_rva, _)) => (*next_function_rva).min(section_end),
+                    None => section_end,
+                };
+                function_end.saturating_sub(function_rva)
+            });
+        result.insert(function_rva, (function_name, function_size));
+    }
+
+    result
+}
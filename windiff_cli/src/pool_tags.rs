@@ -0,0 +1,450 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use iced_x86::{Decoder, DecoderOptions, FlowControl, Instruction, Mnemonic, OpKind, Register};
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    imports::{import_address_table, resolve_import_thunk, ImportedFunction},
+    pe_utils::read_bytes,
+};
+
+/// Pool routines, with the index of their tag argument
+const POOL_ROUTINES: [(&str, usize); 7] = [
+    ("ExAllocatePool2", 2),
+    ("ExAllocatePool3", 2),
+    ("ExAllocatePoolWithTag", 2),
+    ("ExAllocatePoolWithTagPriority", 2),
+    ("ExAllocatePoolWithQuotaTag", 2),
+    ("ExFreePoolWithTag", 1),
+    ("ExFreePool2", 1),
+];
+/// Registers used to pass the first arguments on AMD64
+const ARGUMENT_REGISTERS_AMD64: [Register; 4] =
+    [Register::RCX, Register::RDX, Register::R8, Register::R9];
+/// Name used for call sites that couldn't be attributed to a function
+const UNKNOWN_FUNCTION_NAME: &str = "<unknown>";
+
+/// Value tracked in a register while walking the code
+#[derive(Debug, Clone, Copy)]
+enum RegisterValue {
+    Constant(u32),
+    /// Address of a 4KB page (ARM64 only)
+    Page(u32),
+    /// Pointer to a pool routine (index of its tag argument)
+    PoolRoutine(usize),
+}
+
+/// Extract the pool tags passed to pool routines by a given PE, as (pool tag,
+/// calling function) pairs. Calls are found by disassembling the executable
+/// sections, and only constant tags can be recovered.
+pub fn extract_pool_tags(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, String)>> {
+    let mut pool_routines = PoolRoutines::new(pe, pe_data, symbols);
+    if pool_routines.is_empty() {
+        return Ok(vec![]);
+    }
+    log::trace!("Extracting pool tags");
+
+    let mut tags = vec![];
+    for section in pe
+        .sections
+        .iter()
+        .filter(|section| section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE != 0)
+    {
+        let section_size = section.size_of_raw_data.min(section.virtual_size);
+        let code = match read_bytes(
+            pe_data,
+            section.pointer_to_raw_data as usize,
+            section_size as usize,
+        ) {
+            Ok(code) => code,
+            Err(err) => {
+                log::debug!(
+                    "Skipping truncated section {}: {}",
+                    section.name().unwrap_or_default(),
+                    err
+                );
+                continue;
+            }
+        };
+        if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+            tags.extend(find_pool_tags_amd64(
+                pe,
+                &mut pool_routines,
+                section.virtual_address,
+                code,
+            ));
+        } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+            tags.extend(find_pool_tags_arm64(
+                &mut pool_routines,
+                section.virtual_address,
+                code,
+            ));
+        } else {
+            log::debug!("Unsupported architecture, pool tags won't be extracted");
+            return Ok(vec![]);
+        }
+    }
+
+    Ok(tags
+        .into_iter()
+        .map(|(call_rva, tag)| {
+            let function_name = symbols
+                .range(..=call_rva)
+                .next_back()
+                .filter(|(symbol_rva, _)| **symbol_rva != 0)
+                .map(|(_, symbol_name)| symbol_name.as_str())
+                .unwrap_or(UNKNOWN_FUNCTION_NAME);
+            (format_pool_tag(tag), function_name.to_string())
+        })
+        .collect())
+}
+
+/// Pool routines that can be called by a PE, identified by their IAT slot or
+/// their RVA
+struct PoolRoutines<'p, 'd> {
+    pe: &'p pe::PE<'d>,
+    pe_data: &'d [u8],
+    /// IAT slots of the imported pool routines
+    import_address_table: BTreeMap<u32, ImportedFunction>,
+    /// Routine or thunk RVA -> tag argument index (`None` for other functions)
+    functions: BTreeMap<u32, Option<usize>>,
+}
+
+impl<'p, 'd> PoolRoutines<'p, 'd> {
+    fn new(pe: &'p pe::PE<'d>, pe_data: &'d [u8], symbols: &BTreeMap<u32, String>) -> Self {
+        let import_address_table = import_address_table(pe)
+            .into_iter()
+            .filter(|(_, imported_function)| {
+                tag_argument_index(&imported_function.function).is_some()
+            })
+            .collect();
+        // Pool routines implemented by the PE itself (i.e., ntoskrnl.exe)
+        let functions = symbols
+            .iter()
+            .filter_map(|(symbol_rva, symbol_name)| {
+                Some((*symbol_rva, Some(tag_argument_index(symbol_name)?)))
+            })
+            .collect();
+
+        Self {
+            pe,
+            pe_data,
+            import_address_table,
+            functions,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.import_address_table.is_empty() && self.functions.is_empty()
+    }
+
+    /// Get the tag argument index of the function called through the given
+    /// IAT slot, if it's a pool routine
+    fn tag_argument_from_iat_slot(&self, iat_slot_rva: u32) -> Option<usize> {
+        self.import_address_table
+            .get(&iat_slot_rva)
+            .and_then(|imported_function| tag_argument_index(&imported_function.function))
+    }
+
+    /// Get the tag argument index of the function located at the given RVA,
+    /// if it's a pool routine (or a thunk that jumps to one)
+    fn tag_argument_from_function(&mut self, function_rva: u32) -> Option<usize> {
+        if self.import_address_table.is_empty() {
+            return self.functions.get(&function_rva).copied().flatten();
+        }
+
+        let (pe, pe_data, import_address_table) =
+            (self.pe, self.pe_data, &self.import_address_table);
+        *self.functions.entry(function_rva).or_insert_with(|| {
+            let imported_function =
+                resolve_import_thunk(pe, pe_data, import_address_table, function_rva)?;
+            tag_argument_index(&imported_function.function)
+        })
+    }
+}
+
+fn tag_argument_index(function_name: &str) -> Option<usize> {
+    // 32-bit binaries use decorated names
+    let function_name = function_name.trim_start_matches('_');
+    POOL_ROUTINES
+        .iter()
+        .find(|(routine_name, _)| *routine_name == function_name)
+        .map(|(_, tag_argument_index)| *tag_argument_index)
+}
+
+/// Find calls to pool routines in AMD64 code, as (call RVA, pool tag) pairs.
+/// We want to match call sites such as:
+/// mov r8d, TAG
+/// ...
+/// call qword ptr [rip+IAT_SLOT] ; or a call to a thunk
+/// or:
+/// mov rax, qword ptr [rip+IAT_SLOT]
+/// ...
+/// call rax ; or a call to the CFG dispatch routine
+fn find_pool_tags_amd64(
+    pe: &pe::PE<'_>,
+    pool_routines: &mut PoolRoutines<'_, '_>,
+    section_rva: u32,
+    code: &[u8],
+) -> Vec<(u32, u32)> {
+    let mut register_values: BTreeMap<Register, RegisterValue> = BTreeMap::new();
+    let mut result = vec![];
+
+    let mut decoder = Decoder::with_ip(
+        64,
+        code,
+        pe.image_base + u64::from(section_rva),
+        DecoderOptions::NONE,
+    );
+    let mut instruction = Instruction::default();
+    while decoder.can_decode() {
+        decoder.decode_out(&mut instruction);
+        let instruction_rva = instruction.ip().wrapping_sub(pe.image_base) as u32;
+
+        match instruction.flow_control() {
+            FlowControl::Call => {
+                let target_rva =
+                    instruction.near_branch_target().wrapping_sub(pe.image_base) as u32;
+                if let Some(tag_argument_index) =
+                    pool_routines.tag_argument_from_function(target_rva)
+                {
+                    result.extend(
+                        get_tag_amd64(&register_values, tag_argument_index)
+                            .map(|tag| (instruction_rva, tag)),
+                    );
+                }
+                register_values.clear();
+            }
+            FlowControl::IndirectCall => {
+                let tag_argument_index = if instruction.is_ip_rel_memory_operand() {
+                    let memory_rva = instruction
+                        .ip_rel_memory_address()
+                        .wrapping_sub(pe.image_base) as u32;
+                    // Calls to the CFG dispatch routine use RAX as target
+                    pool_routines
+                        .tag_argument_from_iat_slot(memory_rva)
+                        .or_else(|| match register_values.get(&Register::RAX) {
+                            Some(RegisterValue::PoolRoutine(tag_argument_index)) => {
+                                Some(*tag_argument_index)
+                            }
+                            _ => None,
+                        })
+                } else if instruction.op0_kind() == OpKind::Register {
+                    match register_values.get(&instruction.op0_register().full_register()) {
+                        Some(RegisterValue::PoolRoutine(tag_argument_index)) => {
+                            Some(*tag_argument_index)
+                        }
+                        _ => None,
+                    }
+                } else {
+                    None
+                };
+                if let Some(tag_argument_index) = tag_argument_index {
+                    result.extend(
+                        get_tag_amd64(&register_values, tag_argument_index)
+                            .map(|tag| (instruction_rva, tag)),
+                    );
+                }
+                register_values.clear();
+            }
+            // Register values don't flow past the end of the function (or to
+            // the target of a jump)
+            FlowControl::Return
+            | FlowControl::UnconditionalBranch
+            | FlowControl::IndirectBranch => {
+                register_values.clear();
+            }
+            _ => {
+                if instruction.op0_kind() != OpKind::Register {
+                    continue;
+                }
+                let register = instruction.op0_register().full_register();
+                let value = if matches!(
+                    instruction.mnemonic(),
+                    Mnemonic::Cmp | Mnemonic::Test | Mnemonic::Push
+                ) {
+                    // Register is only read
+                    continue;
+                } else if instruction.mnemonic() != Mnemonic::Mov {
+                    None
+                } else if matches!(
+                    instruction.op1_kind(),
+                    OpKind::Immediate32 | OpKind::Immediate32to64 | OpKind::Immediate64
+                ) {
+                    Some(RegisterValue::Constant(instruction.immediate(1) as u32))
+                } else if instruction.is_ip_rel_memory_operand() {
+                    let memory_rva = instruction
+                        .ip_rel_memory_address()
+                        .wrapping_sub(pe.image_base) as u32;
+                    pool_routines
+                        .tag_argument_from_iat_slot(memory_rva)
+                        .map(RegisterValue::PoolRoutine)
+                } else {
+                    None
+                };
+                match value {
+                    Some(value) => register_values.insert(register, value),
+                    None => register_values.remove(&register),
+                };
+            }
+        }
+    }
+
+    result
+}
+
+fn get_tag_amd64(
+    register_values: &BTreeMap<Register, RegisterValue>,
+    tag_argument_index: usize,
+) -> Option<u32> {
+    match register_values.get(&ARGUMENT_REGISTERS_AMD64[tag_argument_index]) {
+        Some(RegisterValue::Constant(tag)) => Some(*tag),
+        _ => None,
+    }
+}
+
+/// Find calls to pool routines in ARM64 code, as (call RVA, pool tag) pairs.
+/// We want to match call sites such as:
+/// MOVZ W2, TAG_LOW
+/// MOVK W2, TAG_HIGH, LSL #16
+/// ...
+/// BL THUNK ; or BLR Xn, with Xn loaded from an IAT slot
+fn find_pool_tags_arm64(
+    pool_routines: &mut PoolRoutines<'_, '_>,
+    section_rva: u32,
+    code: &[u8],
+) -> Vec<(u32, u32)> {
+    let mut register_values: [Option<RegisterValue>; 32] = [None; 32];
+    let mut result = vec![];
+
+    for (i, instruction_bytes) in code.chunks_exact(4).enumerate() {
+        let instruction_rva = section_rva + 4 * i as u32;
+        let instruction = u32::from_le_bytes([
+            instruction_bytes[0],
+            instruction_bytes[1],
+            instruction_bytes[2],
+            instruction_bytes[3],
+        ]);
+        let rd = (instruction & 0x1f) as usize;
+        let rn = ((instruction >> 5) & 0x1f) as usize;
+
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/BL--Branch-with-Link-
+        if instruction & 0xfc00_0000 == 0x9400_0000 {
+            // Sign-extend the 26-bit immediate
+            let imm = ((instruction & 0x3ff_ffff) << 6) as i32 >> 4;
+            let target_rva = instruction_rva.wrapping_add(imm as u32);
+            if let Some(tag_argument_index) = pool_routines.tag_argument_from_function(target_rva) {
+                result.extend(
+                    get_tag_arm64(&register_values, tag_argument_index)
+                        .map(|tag| (instruction_rva, tag)),
+                );
+            }
+            register_values = [None; 32];
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/BLR--Branch-with-Link-to-Register-
+        else if instruction & 0xffff_fc1f == 0xd63f_0000 {
+            if let Some(RegisterValue::PoolRoutine(tag_argument_index)) = register_values[rn] {
+                result.extend(
+                    get_tag_arm64(&register_values, tag_argument_index)
+                        .map(|tag| (instruction_rva, tag)),
+                );
+            }
+            register_values = [None; 32];
+        }
+        // Register values don't flow past the end of the function (or to the
+        // target of a jump)
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/B--Branch-
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/BR--Branch-to-Register-
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/RET--Return-from-subroutine-
+        else if instruction & 0xfc00_0000 == 0x1400_0000
+            || instruction & 0xffff_fc1f == 0xd61f_0000
+            || instruction & 0xffff_fc1f == 0xd65f_0000
+        {
+            register_values = [None; 32];
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/ADRP--Form-PC-relative-address-to-4KB-page-
+        else if instruction & 0x9f00_0000 == 0x9000_0000 {
+            let immlo = (instruction >> 29) & 0x3;
+            let immhi = (instruction >> 5) & 0x7_ffff;
+            // Sign-extend the 21-bit immediate and convert it to a page offset
+            let imm = (((immhi << 2) | immlo) << 11) as i32 >> 11;
+            register_values[rd] = Some(RegisterValue::Page(
+                (instruction_rva & !0xfff).wrapping_add((imm << 12) as u32),
+            ));
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/LDR--immediate---Load-Register--immediate--
+        else if instruction & 0xffc0_0000 == 0xf940_0000 {
+            let imm12 = (instruction >> 10) & 0xfff;
+            register_values[rd] = match register_values[rn] {
+                Some(RegisterValue::Page(page_address)) => pool_routines
+                    .tag_argument_from_iat_slot(page_address.wrapping_add(imm12 * 8))
+                    .map(RegisterValue::PoolRoutine),
+                _ => None,
+            };
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/MOVZ--Move-wide-with-zero-
+        else if instruction & 0x7f80_0000 == 0x5280_0000 {
+            let hw = (instruction >> 21) & 0x3;
+            let imm16 = (instruction >> 5) & 0xffff;
+            register_values[rd] = Some(RegisterValue::Constant(
+                imm16.checked_shl(16 * hw).unwrap_or_default(),
+            ));
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/MOVK--Move-wide-with-keep-
+        else if instruction & 0x7f80_0000 == 0x7280_0000 {
+            let hw = (instruction >> 21) & 0x3;
+            let imm16 = (instruction >> 5) & 0xffff;
+            register_values[rd] = match (register_values[rd], hw) {
+                (Some(RegisterValue::Constant(value)), 0) => {
+                    Some(RegisterValue::Constant((value & 0xffff_0000) | imm16))
+                }
+                (Some(RegisterValue::Constant(value)), 1) => {
+                    Some(RegisterValue::Constant((value & 0xffff) | (imm16 << 16)))
+                }
+                (value, _) => value,
+            };
+        }
+        // https://developer.arm.com/documentation/ddi0602/2022-06/Base-Instructions/MOV--register---Move--register---an-alias-of-ORR--shifted-register--
+        else if instruction & 0x7fe0_ffe0 == 0x2a00_03e0 {
+            let rm = ((instruction >> 16) & 0x1f) as usize;
+            register_values[rd] = register_values[rm];
+        }
+        // Consider that other instructions overwrite their first operand
+        else {
+            register_values[rd] = None;
+        }
+    }
+
+    result
+}
+
+fn get_tag_arm64(
+    register_values: &[Option<RegisterValue>; 32],
+    tag_argument_index: usize,
+) -> Option<u32> {
+    match register_values[tag_argument_index] {
+        Some(RegisterValue::Constant(tag)) => Some(tag),
+        _ => None,
+    }
+}
+
+/// Format a pool tag as the 4 characters it's made of (e.g., 0x6d4e6f49 ->
+/// "IoNm"), escaping non-printable characters
+fn format_pool_tag(tag: u32) -> String {
+    tag.to_le_bytes()
+        .iter()
+        .map(|c| {
+            if c.is_ascii_graphic() || *c == b' ' {
+                (*c as char).to_string()
+            } else {
+                format!("\\x{:02x}", c)
+            }
+        })
+        .collect()
+}
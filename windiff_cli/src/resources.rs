@@ -0,0 +1,134 @@
+use goblin::pe;
+
+use crate::{
+    error::Result,
+    pe_utils::{read_bytes, read_u16, read_u32, rva_to_offset, utf16_to_string},
+};
+
+const IMAGE_RESOURCE_NAME_IS_STRING: u32 = 0x8000_0000;
+const IMAGE_RESOURCE_DATA_IS_DIRECTORY: u32 = 0x8000_0000;
+const SIZEOF_IMAGE_RESOURCE_DIRECTORY: usize = 16;
+const SIZEOF_IMAGE_RESOURCE_DIRECTORY_ENTRY: usize = 8;
+
+/// Identifier of a resource directory entry (i.e., a resource type or name)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResourceId {
+    Id(u16),
+    Name(String),
+}
+
+/// Resource found in a PE's resource directory
+#[derive(Debug)]
+pub struct Resource<'d> {
+    pub name: ResourceId,
+    pub language: u16,
+    pub data: &'d [u8],
+}
+
+/// Find all the resources of a given type in a PE (for all names and
+/// languages)
+pub fn find_resources<'d>(
+    pe: &pe::PE<'_>,
+    pe_data: &'d [u8],
+    resource_type: &ResourceId,
+) -> Result<Vec<Resource<'d>>> {
+    let resource_directory_rva = match pe
+        .header
+        .optional_header
+        .and_then(|optional_header| {
+            optional_header
+                .data_directories
+                .get_resource_table()
+                .copied()
+        })
+        .filter(|data_directory| data_directory.virtual_address != 0)
+    {
+        Some(data_directory) => data_directory.virtual_address,
+        None => return Ok(vec![]),
+    };
+    let resource_directory_offset = rva_to_offset(resource_directory_rva as usize, pe)?;
+
+    // Resource types
+    let type_directory = match parse_resource_directory(pe_data, resource_directory_offset, 0)?
+        .into_iter()
+        .find(|(type_id, _)| type_id == resource_type)
+    {
+        Some((_, (type_directory, true))) => type_directory,
+        _ => return Ok(vec![]),
+    };
+
+    let mut result = vec![];
+    // Resource names
+    for (name, (name_directory, is_directory)) in
+        parse_resource_directory(pe_data, resource_directory_offset, type_directory)?
+    {
+        if !is_directory {
+            continue;
+        }
+        // Resource languages
+        for (language, (data_entry, is_directory)) in
+            parse_resource_directory(pe_data, resource_directory_offset, name_directory)?
+        {
+            let language = match language {
+                ResourceId::Id(language) if !is_directory => language,
+                _ => continue,
+            };
+
+            // IMAGE_RESOURCE_DATA_ENTRY
+            let data_entry_offset = resource_directory_offset + data_entry as usize;
+            let data_rva = read_u32(pe_data, data_entry_offset)?;
+            let data_size = read_u32(pe_data, data_entry_offset + 4)?;
+            let data_offset = rva_to_offset(data_rva as usize, pe)?;
+            result.push(Resource {
+                name: name.clone(),
+                language,
+                data: read_bytes(pe_data, data_offset, data_size as usize)?,
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse the entries of an `IMAGE_RESOURCE_DIRECTORY` located at the given
+/// offset (relative to the resource directory's root), as (entry identifier,
+/// (entry offset, entry is a directory)) pairs.
+fn parse_resource_directory(
+    pe_data: &[u8],
+    resource_directory_offset: usize,
+    directory_offset: u32,
+) -> Result<Vec<(ResourceId, (u32, bool))>> {
+    let directory_offset = resource_directory_offset + directory_offset as usize;
+    let number_of_named_entries = read_u16(pe_data, directory_offset + 12)?;
+    let number_of_id_entries = read_u16(pe_data, directory_offset + 14)?;
+    let entry_count = number_of_named_entries as usize + number_of_id_entries as usize;
+
+    let mut result = Vec::with_capacity(entry_count);
+    for entry_index in 0..entry_count {
+        let entry_offset = directory_offset
+            + SIZEOF_IMAGE_RESOURCE_DIRECTORY
+            + entry_index * SIZEOF_IMAGE_RESOURCE_DIRECTORY_ENTRY;
+        let name_or_id = read_u32(pe_data, entry_offset)?;
+        let offset_to_data = read_u32(pe_data, entry_offset + 4)?;
+
+        let entry_id = if name_or_id & IMAGE_RESOURCE_NAME_IS_STRING != 0 {
+            // IMAGE_RESOURCE_DIR_STRING_U
+            let name_offset =
+                resource_directory_offset + (name_or_id & !IMAGE_RESOURCE_NAME_IS_STRING) as usize;
+            let name_length = read_u16(pe_data, name_offset)? as usize;
+            let name_data = read_bytes(pe_data, name_offset + 2, 2 * name_length)?;
+            ResourceId::Name(utf16_to_string(name_data))
+        } else {
+            ResourceId::Id(name_or_id as u16)
+        };
+        result.push((
+            entry_id,
+            (
+                offset_to_data & !IMAGE_RESOURCE_DATA_IS_DIRECTORY,
+                offset_to_data & IMAGE_RESOURCE_DATA_IS_DIRECTORY != 0,
+            ),
+        ));
+    }
+
+    Ok(result)
+}
@@ -8,7 +8,7 @@ use resym_core::{
     ResymCoreError,
 };
 
-use crate::error::Result;
+use crate::{configuration::NameFilter, error::Result};
 
 /// Frontend implementation for the application
 /// This struct enables the backend to communicate with us (the frontend)
@@ -54,14 +54,21 @@ impl WinDiffApp {
         })
     }
 
-    pub fn extract_types_from_pdb(&self, pdb_path: &Path) -> Result<Vec<(String, String)>> {
+    /// Reconstruct the types whose name matches the given filter.
+    pub fn extract_types_from_pdb(
+        &self,
+        pdb_path: &Path,
+        filter: &NameFilter,
+    ) -> Result<Vec<(String, String)>> {
         log::trace!("Extracting types from {:?}", pdb_path);
 
         // Load PDB
         self.load_pdb(pdb_path)?;
 
-        // Retrieve a list of all types present in the PDB
-        let type_list = self.list_types()?;
+        // Retrieve a list of all types present in the PDB, and only keep the
+        // ones that pass the filter as reconstruction is expensive
+        let mut type_list = self.list_types()?;
+        type_list.retain(|(type_identifier, _)| filter.is_match(type_identifier));
 
         // Reconstruct all the types
         let mut reconstructed_types = Vec::with_capacity(type_list.len());
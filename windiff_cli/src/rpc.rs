@@ -0,0 +1,188 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    pe_utils::{
+        format_guid, is_code_rva, read_bytes, read_pointer_as_rva, read_u16, read_u32,
+        rva_to_offset, symbolize_unknown_rva,
+    },
+};
+
+// https://learn.microsoft.com/en-us/windows/win32/rpc/rpc-syntax-identifier
+const NDR_TRANSFER_SYNTAX: &str = "8a885d04-1ceb-11c9-9fe8-08002b104860";
+const NDR64_TRANSFER_SYNTAX: &str = "71710533-beba-4937-8319-b5dbef9ccc36";
+/// Upper bound used to reject structures that only look like RPC interfaces
+const MAX_PROCEDURE_COUNT: u32 = 0x1000;
+
+/// RPC interface registered by a server
+#[derive(Serialize, Debug)]
+pub struct RpcInterface {
+    /// Interface version ("major.minor")
+    pub version: String,
+    /// Transfer syntax used by the interface ("NDR" or "NDR64")
+    pub transfer_syntax: String,
+    /// Number of procedures, as declared by the dispatch table
+    pub procedure_count: u32,
+    /// Procedures' implementations (procedure number -> routine name)
+    pub procedures: BTreeMap<u32, String>,
+}
+
+/// Layout of `RPC_SERVER_INTERFACE`, which depends on the pointer size
+struct ServerInterfaceLayout {
+    size: u32,
+    dispatch_table_offset: usize,
+    interpreter_info_offset: usize,
+    pointer_size: usize,
+}
+
+const SERVER_INTERFACE_LAYOUT_32: ServerInterfaceLayout = ServerInterfaceLayout {
+    size: 0x44,
+    dispatch_table_offset: 0x2c,
+    interpreter_info_offset: 0x3c,
+    pointer_size: 4,
+};
+const SERVER_INTERFACE_LAYOUT_64: ServerInterfaceLayout = ServerInterfaceLayout {
+    size: 0x60,
+    dispatch_table_offset: 0x30,
+    interpreter_info_offset: 0x50,
+    pointer_size: 8,
+};
+
+/// Extract RPC server interfaces found in a given PE, as (interface UUID,
+/// interface) pairs.
+///
+/// `RPC_SERVER_INTERFACE` structures are found by scanning the PE's data
+/// sections for structures with a valid size and transfer syntax, and their
+/// procedures are resolved through the associated `MIDL_SERVER_INFO`.
+pub fn extract_rpc_interfaces(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, RpcInterface)>> {
+    log::trace!("Extracting RPC interfaces");
+    let layout = if pe.is_64 {
+        &SERVER_INTERFACE_LAYOUT_64
+    } else {
+        &SERVER_INTERFACE_LAYOUT_32
+    };
+
+    let mut result = vec![];
+    for section in pe.sections.iter().filter(|section| {
+        section.characteristics & pe::section_table::IMAGE_SCN_CNT_INITIALIZED_DATA != 0
+            && section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE == 0
+    }) {
+        let section_start = section.pointer_to_raw_data as usize;
+        let section_size = section.size_of_raw_data.min(section.virtual_size) as usize;
+        for section_offset in (0..section_size.saturating_sub(layout.size as usize)).step_by(4) {
+            let structure_offset = section_start + section_offset;
+            if read_u32(pe_data, structure_offset)? != layout.size {
+                continue;
+            }
+
+            let structure_rva = section.virtual_address as usize + section_offset;
+            match parse_server_interface(pe, pe_data, symbols, layout, structure_offset) {
+                Ok(Some(rpc_interface)) => {
+                    log::trace!(
+                        "Found RPC interface {} (RVA: {:#x})",
+                        rpc_interface.0,
+                        structure_rva
+                    );
+                    result.push(rpc_interface);
+                }
+                Ok(None) => {}
+                // Candidates are found heuristically, so their pointers might
+                // not be valid
+                Err(err) => log::debug!(
+                    "Skipping RPC interface candidate (RVA: {:#x}): {}",
+                    structure_rva,
+                    err
+                ),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parse a potential `RPC_SERVER_INTERFACE` structure. Returns `None` if the
+/// structure isn't a server interface.
+fn parse_server_interface(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    layout: &ServerInterfaceLayout,
+    structure_offset: usize,
+) -> Result<Option<(String, RpcInterface)>> {
+    // RPC_SERVER_INTERFACE.TransferSyntax
+    let transfer_syntax =
+        match format_guid(read_bytes(pe_data, structure_offset + 24, 16)?.try_into()?).as_str() {
+            NDR_TRANSFER_SYNTAX => "NDR",
+            NDR64_TRANSFER_SYNTAX => "NDR64",
+            _ => return Ok(None),
+        };
+    // RPC_SERVER_INTERFACE.InterfaceId
+    let interface_uuid = format_guid(read_bytes(pe_data, structure_offset + 4, 16)?.try_into()?);
+    let major_version = read_u16(pe_data, structure_offset + 20)?;
+    let minor_version = read_u16(pe_data, structure_offset + 22)?;
+
+    // RPC_SERVER_INTERFACE.DispatchTable and RPC_SERVER_INTERFACE.InterpreterInfo
+    let (dispatch_table_rva, server_info_rva) = match (
+        read_pointer_as_rva(pe, pe_data, structure_offset + layout.dispatch_table_offset)?,
+        read_pointer_as_rva(
+            pe,
+            pe_data,
+            structure_offset + layout.interpreter_info_offset,
+        )?,
+    ) {
+        (Some(dispatch_table_rva), Some(server_info_rva)) => (dispatch_table_rva, server_info_rva),
+        // Client interfaces and servers without interpreter info
+        _ => return Ok(None),
+    };
+
+    // RPC_DISPATCH_TABLE.DispatchTableCount
+    let procedure_count = read_u32(pe_data, rva_to_offset(dispatch_table_rva as usize, pe)?)?;
+    if procedure_count == 0 || procedure_count > MAX_PROCEDURE_COUNT {
+        return Ok(None);
+    }
+
+    // MIDL_SERVER_INFO.DispatchTable
+    let server_info_offset = rva_to_offset(server_info_rva as usize, pe)?;
+    let server_routines_rva =
+        match read_pointer_as_rva(pe, pe_data, server_info_offset + layout.pointer_size)? {
+            Some(server_routines_rva) => server_routines_rva,
+            None => return Ok(None),
+        };
+    let server_routines_offset = rva_to_offset(server_routines_rva as usize, pe)?;
+
+    // Symbolize server routines
+    let mut procedures = BTreeMap::new();
+    for procedure_number in 0..procedure_count {
+        let routine_rva = match read_pointer_as_rva(
+            pe,
+            pe_data,
+            server_routines_offset + procedure_number as usize * layout.pointer_size,
+        )? {
+            Some(routine_rva) if is_code_rva(pe, routine_rva) => routine_rva,
+            // Procedures can be left unimplemented
+            _ => continue,
+        };
+        let routine_name = symbols
+            .get(&routine_rva)
+            .cloned()
+            .unwrap_or_else(|| symbolize_unknown_rva(pe, symbols, routine_rva));
+        procedures.insert(procedure_number, routine_name);
+    }
+
+    Ok(Some((
+        interface_uuid,
+        RpcInterface {
+            version: format!("{}.{}", major_version, minor_version),
+            transfer_syntax: transfer_syntax.to_string(),
+            procedure_count,
+            procedures,
+        },
+    )))
+}
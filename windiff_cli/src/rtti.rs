@@ -0,0 +1,173 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    pe_utils::{read_c_string, read_pointer_as_rva, read_u32, rva_to_offset},
+    vtables::{parse_vtable_name, undecorate_qualified_name},
+};
+
+/// Prefixes of decorated type descriptor names, for classes and structures
+const TYPE_DESCRIPTOR_NAME_PREFIXES: [&str; 2] = [".?AV", ".?AU"];
+/// `_RTTICompleteObjectLocator.signature` of 64-bit binaries, which use
+/// image-relative references
+const COL_SIG_REV1: u32 = 1;
+/// Upper bound used to reject malformed hierarchies
+const MAX_BASE_CLASS_COUNT: u32 = 0x400;
+
+/// Base class found in a class hierarchy
+#[derive(Serialize, Debug)]
+pub struct RttiBaseClass {
+    pub name: String,
+    /// Offset of the base class in the class (`PMD.mdisp`)
+    pub offset: i32,
+    pub virtual_base: bool,
+    /// Whether the class inherits from the base class directly
+    pub direct: bool,
+}
+
+/// Extract the class hierarchies described by the RTTI of the classes whose
+/// vftables are found in a given PE's debug symbols, as (class name, base
+/// classes) pairs.
+pub fn extract_rtti(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, Vec<RttiBaseClass>)>> {
+    log::trace!("Extracting RTTI");
+    let rtti_parser = RttiParser { pe, pe_data };
+    let pointer_size = if pe.is_64 { 8 } else { 4 };
+
+    let mut result = vec![];
+    for (vtable_rva, symbol_name) in symbols {
+        if parse_vtable_name(symbol_name).is_none() || *vtable_rva < pointer_size {
+            continue;
+        }
+
+        // The complete object locator is referenced by the slot that precedes
+        // the vftable
+        let locator_rva = match rva_to_offset((vtable_rva - pointer_size) as usize, pe).and_then(
+            |locator_pointer_offset| read_pointer_as_rva(pe, pe_data, locator_pointer_offset),
+        ) {
+            Ok(Some(locator_rva)) => locator_rva,
+            // Compiled without RTTI
+            _ => continue,
+        };
+        match rtti_parser.parse_complete_object_locator(locator_rva) {
+            Ok(Some(hierarchy)) => result.push(hierarchy),
+            Ok(None) => {}
+            Err(err) => log::debug!("Failed to parse RTTI of '{}': {}", symbol_name, err),
+        }
+    }
+
+    Ok(result)
+}
+
+struct RttiParser<'p, 'd> {
+    pe: &'p pe::PE<'d>,
+    pe_data: &'d [u8],
+}
+
+impl RttiParser<'_, '_> {
+    /// Parse a `_RTTICompleteObjectLocator` structure. Returns `None` if the
+    /// structure isn't a valid complete object locator.
+    fn parse_complete_object_locator(
+        &self,
+        locator_rva: u32,
+    ) -> Result<Option<(String, Vec<RttiBaseClass>)>> {
+        let locator_offset = rva_to_offset(locator_rva as usize, self.pe)?;
+        let signature = read_u32(self.pe_data, locator_offset)?;
+        if signature != u32::from(self.pe.is_64) {
+            return Ok(None);
+        }
+        // `pSelf` must point back to the locator in 64-bit binaries
+        if signature == COL_SIG_REV1 && read_u32(self.pe_data, locator_offset + 20)? != locator_rva
+        {
+            return Ok(None);
+        }
+
+        let class_name = match self.read_type_name(self.read_reference(locator_offset + 12)?)? {
+            Some(class_name) => class_name,
+            None => return Ok(None),
+        };
+        let hierarchy_offset =
+            rva_to_offset(self.read_reference(locator_offset + 16)? as usize, self.pe)?;
+
+        // _RTTIClassHierarchyDescriptor
+        let base_class_count = read_u32(self.pe_data, hierarchy_offset + 8)?;
+        if base_class_count > MAX_BASE_CLASS_COUNT {
+            return Ok(None);
+        }
+        let base_class_array_offset = rva_to_offset(
+            self.read_reference(hierarchy_offset + 12)? as usize,
+            self.pe,
+        )?;
+
+        // Base classes are listed in pre-order, starting with the class itself
+        let mut base_classes = vec![];
+        let mut next_direct_base_index = 1;
+        for base_class_index in 1..base_class_count as usize {
+            // _RTTIBaseClassDescriptor
+            let descriptor_offset = rva_to_offset(
+                self.read_reference(base_class_array_offset + 4 * base_class_index)? as usize,
+                self.pe,
+            )?;
+            let base_class_name = self
+                .read_type_name(self.read_reference(descriptor_offset)?)?
+                .unwrap_or_default();
+            let contained_base_count = read_u32(self.pe_data, descriptor_offset + 4)? as usize;
+            // PMD
+            let mdisp = read_u32(self.pe_data, descriptor_offset + 8)? as i32;
+            let pdisp = read_u32(self.pe_data, descriptor_offset + 12)? as i32;
+
+            let direct = base_class_index == next_direct_base_index;
+            if direct {
+                next_direct_base_index += 1 + contained_base_count;
+            }
+            base_classes.push(RttiBaseClass {
+                name: base_class_name,
+                offset: mdisp,
+                // Virtual bases are located through the vbtable
+                virtual_base: pdisp != -1,
+                direct,
+            });
+        }
+
+        Ok(Some((class_name, base_classes)))
+    }
+
+    /// Read a reference to an RTTI structure, as an RVA (64-bit binaries use
+    /// image-relative references, and 32-bit binaries use pointers)
+    fn read_reference(&self, offset: usize) -> Result<u32> {
+        let reference = read_u32(self.pe_data, offset)?;
+        Ok(if self.pe.is_64 {
+            reference
+        } else {
+            reference.wrapping_sub(self.pe.image_base as u32)
+        })
+    }
+
+    /// Read the undecorated name of the type described by a `TypeDescriptor`
+    /// structure. Returns `None` if the type isn't a class or a structure.
+    fn read_type_name(&self, type_descriptor_rva: u32) -> Result<Option<String>> {
+        let pointer_size = if self.pe.is_64 { 8 } else { 4 };
+        let type_descriptor_offset = rva_to_offset(type_descriptor_rva as usize, self.pe)?;
+        let decorated_name =
+            read_c_string(self.pe_data, type_descriptor_offset + 2 * pointer_size)?;
+
+        Ok(TYPE_DESCRIPTOR_NAME_PREFIXES
+            .iter()
+            .find_map(|prefix| decorated_name.strip_prefix(prefix))
+            .and_then(|qualified_name| qualified_name.strip_suffix("@@"))
+            .map(|qualified_name| {
+                // Keep the decorated name of templates and other complex types
+                if qualified_name.contains(['?', '$']) {
+                    decorated_name.clone()
+                } else {
+                    undecorate_qualified_name(qualified_name)
+                }
+            }))
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{error::Result, pe_utils::read_bytes};
+
+/// Minimum length (in characters) of the harvested strings, unless configured
+/// otherwise
+pub const DEFAULT_MIN_STRING_LENGTH: usize = 5;
+/// Sections whose content is already covered by other extraction kinds
+const IGNORED_SECTIONS: [&str; 1] = [".rsrc"];
+
+const REGISTRY_KEY_PREFIXES: [&str; 8] = [
+    "\\registry\\",
+    "hkey_",
+    "hklm\\",
+    "hkcu\\",
+    "hkcr\\",
+    "hku\\",
+    "software\\",
+    "system\\currentcontrolset\\",
+];
+const FILE_EXTENSIONS: [&str; 12] = [
+    ".cat", ".dat", ".dll", ".etl", ".exe", ".inf", ".ini", ".json", ".log", ".mui", ".sys", ".xml",
+];
+
+/// String found in a binary's read-only data
+#[derive(Serialize, Debug)]
+pub struct BinaryString {
+    pub kind: StringKind,
+    /// Encodings the string was found in
+    pub encodings: BTreeSet<StringEncoding>,
+    /// Symbols located at the string's addresses
+    pub symbols: BTreeSet<String>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StringKind {
+    RegistryKey,
+    ObjectPath,
+    Guid,
+    Sddl,
+    Url,
+    FilePath,
+    Other,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum StringEncoding {
+    Ascii,
+    Utf16,
+}
+
+/// Harvest the NUL-terminated ASCII and UTF-16LE strings of at least
+/// `min_length` characters found in the read-only data sections of a given
+/// PE, as (string, string information) pairs. Strings are attributed to the
+/// symbols located at their RVAs when available, and strings found several
+/// times are only listed once.
+pub fn extract_strings(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    min_length: usize,
+) -> Result<Vec<(String, BinaryString)>> {
+    log::trace!("Extracting strings (minimum length: {})", min_length);
+    let min_length = min_length.max(1);
+
+    let mut result: BTreeMap<String, BinaryString> = BTreeMap::new();
+    for section in pe.sections.iter().filter(|section| {
+        section.characteristics & pe::section_table::IMAGE_SCN_CNT_INITIALIZED_DATA != 0
+            && section.characteristics
+                & (pe::section_table::IMAGE_SCN_MEM_WRITE
+                    | pe::section_table::IMAGE_SCN_MEM_EXECUTE
+                    | pe::section_table::IMAGE_SCN_MEM_DISCARDABLE)
+                == 0
+            && !section
+                .name()
+                .map(|name| IGNORED_SECTIONS.contains(&name))
+                .unwrap_or_default()
+    }) {
+        let section_size = section.size_of_raw_data.min(section.virtual_size) as usize;
+        let section_data =
+            match read_bytes(pe_data, section.pointer_to_raw_data as usize, section_size) {
+                Ok(section_data) => section_data,
+                Err(err) => {
+                    log::debug!(
+                        "Skipping truncated section {}: {}",
+                        section.name().unwrap_or_default(),
+                        err
+                    );
+                    continue;
+                }
+            };
+
+        let mut section_strings = find_ascii_strings(section_data, min_length);
+        section_strings.extend(find_utf16_strings(section_data, min_length));
+        for (section_offset, string, encoding) in section_strings {
+            let rva = section.virtual_address + section_offset as u32;
+            let string_info = result
+                .entry(string)
+                .or_insert_with_key(|string| BinaryString {
+                    kind: classify_string(string),
+                    encodings: BTreeSet::new(),
+                    symbols: BTreeSet::new(),
+                });
+            string_info.encodings.insert(encoding);
+            string_info.symbols.extend(symbols.get(&rva).cloned());
+        }
+    }
+
+    Ok(result.into_iter().collect())
+}
+
+/// Find NUL-terminated runs of printable ASCII characters
+fn find_ascii_strings(data: &[u8], min_length: usize) -> Vec<(usize, String, StringEncoding)> {
+    let mut result = vec![];
+    let mut string_start = 0;
+    for (offset, c) in data.iter().enumerate() {
+        if is_printable(*c) {
+            continue;
+        }
+        if *c == 0 && offset - string_start >= min_length {
+            let string = String::from_utf8_lossy(&data[string_start..offset]).to_string();
+            result.push((string_start, string, StringEncoding::Ascii));
+        }
+        string_start = offset + 1;
+    }
+
+    result
+}
+
+/// Find NUL-terminated runs of printable ASCII characters encoded as
+/// UTF-16LE (wide strings are 2-byte aligned)
+fn find_utf16_strings(data: &[u8], min_length: usize) -> Vec<(usize, String, StringEncoding)> {
+    let mut result = vec![];
+    let mut string_start = 0;
+    let mut string = String::new();
+    for (index, c) in data.chunks_exact(2).enumerate() {
+        if c[1] == 0 && is_printable(c[0]) {
+            string.push(c[0] as char);
+            continue;
+        }
+        if c == [0, 0] && string.len() >= min_length {
+            result.push((string_start, string.clone(), StringEncoding::Utf16));
+        }
+        string.clear();
+        string_start = 2 * (index + 1);
+    }
+
+    result
+}
+
+fn is_printable(c: u8) -> bool {
+    c == b'\t' || (0x20..0x7f).contains(&c)
+}
+
+/// Classify a string based on its content
+fn classify_string(string: &str) -> StringKind {
+    let lowercase_string = string.to_lowercase();
+    if is_guid(string) {
+        StringKind::Guid
+    } else if is_sddl(string) {
+        StringKind::Sddl
+    } else if is_url(&lowercase_string) {
+        StringKind::Url
+    } else if REGISTRY_KEY_PREFIXES
+        .iter()
+        .any(|prefix| lowercase_string.starts_with(prefix))
+    {
+        StringKind::RegistryKey
+    } else if string.starts_with('\\') && !string.starts_with("\\\\") && string.len() > 1 {
+        StringKind::ObjectPath
+    } else if is_file_path(&lowercase_string) {
+        StringKind::FilePath
+    } else {
+        StringKind::Other
+    }
+}
+
+/// Match "xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx", optionally enclosed in braces
+fn is_guid(string: &str) -> bool {
+    let guid = string
+        .strip_prefix('{')
+        .and_then(|guid| guid.strip_suffix('}'))
+        .unwrap_or(string);
+    let groups: Vec<&str> = guid.split('-').collect();
+
+    groups.len() == 5
+        && groups.iter().zip([8, 4, 4, 4, 12]).all(|(group, length)| {
+            group.len() == length && group.chars().all(|c| c.is_ascii_hexdigit())
+        })
+}
+
+/// Match security descriptors such as "O:BAG:SYD:(A;;GA;;;SY)"
+fn is_sddl(string: &str) -> bool {
+    ["O:", "G:", "D:", "S:"]
+        .iter()
+        .any(|prefix| string.starts_with(prefix))
+        && string.contains('(')
+        && string.contains(';')
+        && string.ends_with(')')
+}
+
+/// Match "scheme://..."
+fn is_url(lowercase_string: &str) -> bool {
+    lowercase_string
+        .split_once("://")
+        .map(|(scheme, _)| {
+            scheme.len() > 1
+                && scheme.starts_with(|c: char| c.is_ascii_lowercase())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "+-.".contains(c))
+        })
+        .unwrap_or_default()
+}
+
+/// Match absolute paths ("C:\...", "\\server\...", "%SystemRoot%\...") and
+/// file names with a common extension
+fn is_file_path(lowercase_string: &str) -> bool {
+    let bytes = lowercase_string.as_bytes();
+    let is_absolute =
+        (bytes.len() > 2 && bytes[0].is_ascii_lowercase() && bytes[1] == b':' && bytes[2] == b'\\')
+            || lowercase_string.starts_with("\\\\")
+            || (lowercase_string.starts_with('%') && lowercase_string.contains("%\\"));
+
+    is_absolute
+        || (!lowercase_string.contains(' ')
+            && FILE_EXTENSIONS
+                .iter()
+                .any(|extension| lowercase_string.ends_with(extension)))
+}
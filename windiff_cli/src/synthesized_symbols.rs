@@ -0,0 +1,137 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use goblin::pe;
+
+use crate::{
+    error::Result,
+    pe_utils::{delimit_functions, is_code_rva, read_u32, runtime_functions, rva_to_offset},
+};
+
+/// Mask of the `GuardFlags` bits that give the number of extra bytes
+/// following each RVA in the CFG function table
+const IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_MASK: u32 = 0xf000_0000;
+const IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_SHIFT: u32 = 28;
+
+/// Synthesize the procedures of a PE that has no PDB, as (RVA, (name,
+/// length)) pairs. Function starts are derived from the exception directory,
+/// exports, the CFG function table, TLS callbacks and the entry point.
+/// Functions are named after their export if they have one, or "sub_<rva>"
+/// otherwise.
+pub fn synthesize_procedures(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+) -> Result<BTreeMap<u32, (String, u32)>> {
+    log::trace!("Synthesizing procedures");
+
+    let mut function_starts: BTreeSet<u32> = runtime_functions(pe)?.into_keys().collect();
+    let mut add_function_start = |function_rva: u32| {
+        if is_code_rva(pe, function_rva) {
+            function_starts.insert(function_rva);
+        }
+    };
+    // Exports
+    let mut export_names = BTreeMap::new();
+    for export in &pe.exports {
+        if export.reexport.is_none() {
+            add_function_start(export.rva as u32);
+            if let Some(export_name) = export.name {
+                export_names.entry(export.rva as u32).or_insert(export_name);
+            }
+        }
+    }
+    // CFG
+    for function_rva in guard_cf_functions(pe, pe_data)? {
+        add_function_start(function_rva);
+    }
+    // TLS callbacks
+    if let Some(tls_data) = &pe.tls_data {
+        for callback_va in &tls_data.callbacks {
+            add_function_start(callback_va.wrapping_sub(pe.image_base) as u32);
+        }
+    }
+    // Entry point
+    if pe.entry != 0 {
+        add_function_start(pe.entry);
+    }
+
+    let functions = function_starts
+        .into_iter()
+        .map(|function_rva| {
+            let function_name = match export_names.get(&function_rva) {
+                Some(export_name) => export_name.to_string(),
+                None => format!("sub_{:x}", function_rva),
+            };
+            (function_rva, (function_name, None))
+        })
+        .collect();
+
+    Ok(delimit_functions(pe, functions))
+}
+
+/// Read the CFG function table, which lists valid indirect call targets
+fn guard_cf_functions(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<u32>> {
+    let load_config_data = match &pe.load_config_data {
+        Some(load_config_data) => load_config_data,
+        None => return Ok(vec![]),
+    };
+    let (table_va, function_count, guard_flags) = match (
+        load_config_data.directory.guard_cf_function_table,
+        load_config_data.directory.guard_cf_function_count,
+        load_config_data.directory.guard_flags,
+    ) {
+        (Some(table_va), Some(function_count), Some(guard_flags)) if table_va != 0 => {
+            (table_va, function_count, guard_flags)
+        }
+        _ => return Ok(vec![]),
+    };
+
+    let entry_size = 4
+        + ((guard_flags & IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_MASK)
+            >> IMAGE_GUARD_CF_FUNCTION_TABLE_SIZE_SHIFT) as usize;
+    let table_offset = rva_to_offset(table_va.wrapping_sub(pe.image_base) as usize, pe)?;
+    (0..function_count as usize)
+        .map(|function_index| read_u32(pe_data, table_offset + function_index * entry_size))
+        .collect()
+}
+
+/// Convert procedures to symbols, as (RVA, name) pairs
+pub fn procedure_symbols(procedures: &BTreeMap<u32, (String, u32)>) -> BTreeMap<u32, String> {
+    procedures
+        .iter()
+        .map(|(function_rva, (function_name, _))| (*function_rva, function_name.clone()))
+        .collect()
+}
+
+/// Only keep the procedures named after an export. Unlike "sub_<rva>" names,
+/// these don't change when functions move between builds, so they can be
+/// diffed.
+pub fn exported_procedures(
+    pe: &pe::PE<'_>,
+    procedures: &BTreeMap<u32, (String, u32)>,
+) -> BTreeMap<u32, (String, u32)> {
+    let export_names: BTreeSet<(u32, &str)> = pe
+        .exports
+        .iter()
+        .filter(|export| export.reexport.is_none())
+        .filter_map(|export| Some((export.rva as u32, export.name?)))
+        .collect();
+
+    procedures
+        .iter()
+        .filter(|(function_rva, (function_name, _))| {
+            export_names.contains(&(**function_rva, function_name.as_str()))
+        })
+        .map(|(function_rva, procedure)| (*function_rva, procedure.clone()))
+        .collect()
+}
+
+/// Get the names of the functions exported by a PE, formatted like the debug
+/// symbols of functions. Unlike "sub_<rva>" names, these don't change when
+/// functions move between builds, so they can be diffed.
+pub fn exported_function_symbols(pe: &pe::PE<'_>) -> BTreeSet<String> {
+    pe.exports
+        .iter()
+        .filter(|export| export.reexport.is_none() && is_code_rva(pe, export.rva as u32))
+        .filter_map(|export| Some(format!("{}()", export.name?)))
+        .collect()
+}
@@ -0,0 +1,232 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    configuration::OSArchitecture,
+    database::{database_path, os_path_suffix, read_compressed_json, write_compressed_json},
+    error::Result,
+    winbindex::DownloadedPEVersion,
+};
+
+/// Binaries that contain syscall stubs
+const USER_SYSCALL_BINARIES: [&str; 2] = ["ntdll.dll", "win32u.dll"];
+/// Binaries that contain service tables
+const KERNEL_SYSCALL_BINARIES: [&str; 2] = ["ntoskrnl.exe", "win32k.sys"];
+
+/// Service table index bits in syscall identifiers
+const SERVICE_TABLE_SHIFT: u32 = 12;
+
+/// Syscall interface of an OS version, built by joining user-mode stubs with
+/// kernel-mode service table entries.
+#[derive(Serialize, Debug, Default)]
+pub struct SyscallInterface {
+    /// Syscall ID -> syscall metadata
+    pub syscalls: BTreeMap<u32, SyscallInterfaceEntry>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct SyscallInterfaceEntry {
+    /// Stub found in ntdll.dll or win32u.dll
+    pub user: Option<SyscallLocation>,
+    /// Routine found in ntoskrnl.exe's or win32k.sys's service table
+    pub kernel: Option<SyscallLocation>,
+    /// Number of arguments passed on the stack, taken from the kernel's
+    /// argument table (register arguments aren't included)
+    pub stack_argument_count: Option<u32>,
+    /// Total number of arguments, including the ones passed in registers. Only
+    /// known if the syscall takes arguments on the stack, or if the
+    /// architecture passes none in registers.
+    pub argument_count: Option<u32>,
+    /// Set if both sides of the interface don't agree
+    pub mismatch: Option<SyscallInterfaceMismatch>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SyscallLocation {
+    /// Binary name
+    pub location: String,
+    /// Stub or routine name
+    pub identifier: String,
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyscallInterfaceMismatch {
+    MissingKernelRoutine,
+    MissingUserStub,
+    NameMismatch,
+}
+
+/// Syscall-related subset of a binary database
+#[derive(Deserialize, Debug, Default)]
+struct SyscallDatabase {
+    #[serde(default)]
+    syscalls: BTreeMap<u32, String>,
+    #[serde(default)]
+    syscall_stack_argument_counts: BTreeMap<u32, u32>,
+}
+
+/// Generate a syscall interface file for each OS version, from previously
+/// generated databases.
+pub async fn generate_syscall_interfaces(
+    downloaded_binaries: &[(DownloadedPEVersion, Option<PathBuf>)],
+    output_directory: &Path,
+) -> Result<()> {
+    let os_suffixes: BTreeMap<String, OSArchitecture> = downloaded_binaries
+        .iter()
+        .map(|(pe_version, _)| (os_path_suffix(pe_version), pe_version.architecture))
+        .collect();
+
+    for (os_suffix, architecture) in os_suffixes {
+        log::trace!("Generating syscall interface for '{}'", os_suffix);
+
+        let user_databases =
+            load_syscall_databases(&USER_SYSCALL_BINARIES, &os_suffix, output_directory).await;
+        let kernel_databases =
+            load_syscall_databases(&KERNEL_SYSCALL_BINARIES, &os_suffix, output_directory).await;
+        if user_databases.is_empty() && kernel_databases.is_empty() {
+            // Nothing to join for this OS version
+            continue;
+        }
+
+        let syscall_interface =
+            join_syscall_databases(&user_databases, &kernel_databases, architecture);
+        write_compressed_json(
+            &syscall_interface,
+            &output_directory.join(format!("syscall_interface_{}.json.gz", os_suffix)),
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Load the syscall-related part of the databases generated for the given
+/// binaries, skipping databases that don't exist or contain no syscalls.
+async fn load_syscall_databases(
+    binary_names: &[&'static str],
+    os_suffix: &str,
+    output_directory: &Path,
+) -> Vec<(&'static str, SyscallDatabase)> {
+    let mut result = vec![];
+    for binary_name in binary_names {
+        let path = database_path(output_directory, binary_name, os_suffix);
+        if !path.exists() {
+            continue;
+        }
+
+        match read_compressed_json::<SyscallDatabase>(&path).await {
+            Ok(database) if !database.syscalls.is_empty() => result.push((*binary_name, database)),
+            Ok(_) => {}
+            Err(err) => log::warn!("Failed to read database {:?}: {}", path, err),
+        }
+    }
+
+    result
+}
+
+fn join_syscall_databases(
+    user_databases: &[(&str, SyscallDatabase)],
+    kernel_databases: &[(&str, SyscallDatabase)],
+    architecture: OSArchitecture,
+) -> SyscallInterface {
+    let mut syscall_interface = SyscallInterface::default();
+    let register_argument_count = register_argument_count(architecture);
+
+    // User-mode stubs
+    for (binary_name, database) in user_databases {
+        for (syscall_id, stub_name) in &database.syscalls {
+            syscall_interface
+                .syscalls
+                .entry(*syscall_id)
+                .or_default()
+                .user = Some(SyscallLocation {
+                location: binary_name.to_string(),
+                identifier: stub_name.clone(),
+            });
+        }
+    }
+    // Kernel-mode routines
+    for (binary_name, database) in kernel_databases {
+        for (syscall_id, routine_name) in &database.syscalls {
+            let entry = syscall_interface.syscalls.entry(*syscall_id).or_default();
+            entry.kernel = Some(SyscallLocation {
+                location: binary_name.to_string(),
+                identifier: routine_name.clone(),
+            });
+            entry.stack_argument_count = database
+                .syscall_stack_argument_counts
+                .get(syscall_id)
+                .copied();
+            entry.argument_count = entry.stack_argument_count.and_then(|stack_argument_count| {
+                if stack_argument_count != 0 {
+                    Some(stack_argument_count + register_argument_count)
+                } else if register_argument_count == 0 {
+                    Some(0)
+                } else {
+                    // Anywhere between 0 and `register_argument_count`
+                    None
+                }
+            });
+        }
+    }
+
+    // Flag entries where both sides don't agree. Missing sides are only
+    // reported for service tables that have been processed on that side.
+    let user_tables = service_tables(user_databases);
+    let kernel_tables = service_tables(kernel_databases);
+    for (syscall_id, entry) in syscall_interface.syscalls.iter_mut() {
+        let service_table = syscall_id >> SERVICE_TABLE_SHIFT;
+        entry.mismatch = match (&entry.user, &entry.kernel) {
+            (Some(user), Some(kernel)) => {
+                (!syscall_names_match(&user.identifier, &kernel.identifier))
+                    .then_some(SyscallInterfaceMismatch::NameMismatch)
+            }
+            (Some(_), None) if kernel_tables.contains(&service_table) => {
+                Some(SyscallInterfaceMismatch::MissingKernelRoutine)
+            }
+            (None, Some(_)) if user_tables.contains(&service_table) => {
+                Some(SyscallInterfaceMismatch::MissingUserStub)
+            }
+            _ => None,
+        };
+    }
+
+    syscall_interface
+}
+
+/// Number of syscall arguments passed in registers, which argument tables don't
+/// describe
+fn register_argument_count(architecture: OSArchitecture) -> u32 {
+    match architecture {
+        OSArchitecture::I386 | OSArchitecture::Wow64 => 0,
+        OSArchitecture::Amd64 | OSArchitecture::Arm => 4,
+        OSArchitecture::Arm64 => 8,
+    }
+}
+
+/// List the service tables (e.g., 0 for ntoskrnl.exe and 1 for win32k.sys)
+/// covered by the given databases
+fn service_tables(databases: &[(&str, SyscallDatabase)]) -> BTreeSet<u32> {
+    databases
+        .iter()
+        .flat_map(|(_, database)| database.syscalls.keys())
+        .map(|syscall_id| syscall_id >> SERVICE_TABLE_SHIFT)
+        .collect()
+}
+
+/// Check if a user-mode stub and a kernel-mode routine names refer to the same
+/// syscall (e.g., "ZwClose" and "NtClose").
+fn syscall_names_match(user_name: &str, kernel_name: &str) -> bool {
+    fn strip_prefix(name: &str) -> &str {
+        name.strip_prefix("Nt")
+            .or_else(|| name.strip_prefix("Zw"))
+            .unwrap_or(name)
+    }
+
+    strip_prefix(user_name) == strip_prefix(kernel_name)
+}
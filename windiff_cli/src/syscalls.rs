@@ -1,19 +1,47 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use goblin::pe;
 
 use crate::{
+    arm64x::{extract_ec_exports, HybridCodeKind, HybridMetadata},
     configuration::OSArchitecture,
     error::{Result, WinDiffError},
-    pdb::Pdb,
+    imports::{import_address_table, resolve_import_thunk, ImportedFunction},
+    pe_utils::{is_code_rva, read_u64, rva_to_offset, symbolize_unknown_rva},
 };
 
+/// Number of service table entries checked to determine the table's format
+const SERVICE_TABLE_SAMPLE_SIZE: u32 = 8;
+
+/// Syscall information extracted from a PE
+#[derive(Debug, Default)]
+pub struct ExtractedSyscalls {
+    /// Syscall identifiers and the name of their stub or implementation
+    pub syscalls: Vec<(u32, String)>,
+    /// Number of arguments passed on the stack to each syscall (for kernel
+    /// binaries only)
+    pub stack_argument_counts: Vec<(u32, u32)>,
+    /// Syscalls whose implementation couldn't be found in the PDB's symbols
+    /// (for kernel binaries only)
+    pub unresolved_syscalls: Vec<u32>,
+    /// Binary and function implementing each syscall, after following import
+    /// thunks (for win32k.sys only)
+    pub implementations: Vec<(u32, ImportedFunction)>,
+    /// Filtered service tables and, for each syscall, whether the filter allows
+    /// it (for kernel binaries only)
+    pub filters: Vec<SyscallFilter>,
+}
+
+/// Name of a filtered service table and, for each syscall, whether the filter
+/// allows it
+pub type SyscallFilter = (String, Vec<(u32, bool)>);
+
 /// Extract syscalls found in a given PE (if supported for that PE).
 pub fn extract_syscalls(
-    pe: pe::PE<'_>,
+    pe: &pe::PE<'_>,
     pe_data: &[u8],
-    pdb: &mut Pdb,
-) -> Result<Vec<(u32, String)>> {
+    symbols: &BTreeMap<u32, String>,
+) -> Result<ExtractedSyscalls> {
     // Get the PE's name
     let pe_name = pe
         .export_data
@@ -29,38 +57,64 @@ pub fn extract_syscalls(
 
     // Handle ntoskrnl.exe
     if pe_name.eq_ignore_ascii_case("ntoskrnl.exe") {
-        let symbols = pdb.extract_symbols_with_offset(false)?;
-        return extract_ntoskrnl_syscalls(&pe, pe_data, &symbols);
+        return extract_ntoskrnl_syscalls(pe, pe_data, symbols);
     }
     // Handle win32k.sys
     if pe_name.eq_ignore_ascii_case("win32k.sys") {
-        let symbols = pdb.extract_symbols_with_offset(false)?;
-        return extract_win32k_syscalls(&pe, pe_data, &symbols);
+        return extract_win32k_syscalls(pe, pe_data, symbols);
     }
 
     // Handle user-mode binaries (i.e., ntdll.dll and win32u.dll)
     // Select syscall extraction implementation depending on the PE's target architecture
-    extract_syscalls_from_user_binaries(&pe, pe_data)
+    Ok(ExtractedSyscalls {
+        syscalls: extract_syscalls_from_user_binaries(pe, pe_data)?,
+        ..Default::default()
+    })
 }
 
 fn extract_ntoskrnl_syscalls(
     pe: &pe::PE<'_>,
     pe_data: &[u8],
     symbols: &BTreeMap<u32, String>,
-) -> Result<Vec<(u32, String)>> {
-    //  Find the service table 'W32pServiceTable' and 'W32pServiceLimit'
+) -> Result<ExtractedSyscalls> {
+    //  Find the service table 'KiServiceTable' and 'KiServiceLimit'
     let service_table_info =
         find_service_table(pe, pe_data, symbols, "KiServiceTable", "KiServiceLimit")?;
 
     // Extract syscalls from the service table
-    extract_syscalls_from_service_table(pe, pe_data, symbols, &service_table_info)
+    let service_table_entries = extract_service_table_entries(pe, pe_data, &service_table_info)?;
+    let (syscalls, unresolved_syscalls) =
+        symbolize_service_table_entries(pe, symbols, &service_table_entries);
+
+    Ok(ExtractedSyscalls {
+        syscalls,
+        unresolved_syscalls,
+        // Extract argument sizes from the argument table
+        stack_argument_counts: extract_stack_argument_counts(
+            pe,
+            pe_data,
+            symbols,
+            "KiArgumentTable",
+            service_table_info.1,
+        )?,
+        // Extract filtered service tables
+        filters: extract_service_table_filters(
+            pe,
+            pe_data,
+            symbols,
+            "KiServiceTable",
+            "KiServiceLimit",
+            &service_table_entries,
+        ),
+        ..Default::default()
+    })
 }
 
 fn extract_win32k_syscalls(
     pe: &pe::PE<'_>,
     pe_data: &[u8],
     symbols: &BTreeMap<u32, String>,
-) -> Result<Vec<(u32, String)>> {
+) -> Result<ExtractedSyscalls> {
     const WIN32K_SYSCALL_TABLE_ID: u32 = 0x1000;
 
     //  Find the service table 'W32pServiceTable' and 'W32pServiceLimit'
@@ -68,15 +122,73 @@ fn extract_win32k_syscalls(
         find_service_table(pe, pe_data, symbols, "W32pServiceTable", "W32pServiceLimit")?;
 
     // Extract syscalls from the service table
-    extract_syscalls_from_service_table(pe, pe_data, symbols, &service_table_info)
-        // Include win32k's table identifier in the syscall number
-        .map(|mut syscalls| {
-            syscalls
-                .iter_mut()
-                .for_each(|syscall: &mut (u32, String)| syscall.0 |= WIN32K_SYSCALL_TABLE_ID);
-
-            syscalls
+    let service_table_entries = extract_service_table_entries(pe, pe_data, &service_table_info)?;
+    let (syscalls, unresolved_syscalls) =
+        symbolize_service_table_entries(pe, symbols, &service_table_entries);
+
+    // Follow stubs that forward syscalls to win32kbase.sys or win32kfull.sys
+    let import_address_table = import_address_table(pe);
+    let implementations = service_table_entries
+        .iter()
+        .zip(syscalls.iter())
+        .map(|((syscall_id, syscall_impl_rva), (_, symbol_name))| {
+            let implementation =
+                resolve_import_thunk(pe, pe_data, &import_address_table, *syscall_impl_rva)
+                    .cloned()
+                    .unwrap_or_else(|| ImportedFunction {
+                        module: pe.name.unwrap_or_default().to_string(),
+                        function: symbol_name.clone(),
+                    });
+            (*syscall_id, implementation)
         })
+        .collect();
+
+    let mut extracted_syscalls = ExtractedSyscalls {
+        syscalls,
+        unresolved_syscalls,
+        implementations,
+        // Extract argument sizes from the argument table
+        stack_argument_counts: extract_stack_argument_counts(
+            pe,
+            pe_data,
+            symbols,
+            "W32pArgumentTable",
+            service_table_info.1,
+        )?,
+        // Extract filtered service tables
+        filters: extract_service_table_filters(
+            pe,
+            pe_data,
+            symbols,
+            "W32pServiceTable",
+            "W32pServiceLimit",
+            &service_table_entries,
+        ),
+    };
+    // Include win32k's table identifier in the syscall numbers
+    extracted_syscalls
+        .syscalls
+        .iter_mut()
+        .for_each(|syscall| syscall.0 |= WIN32K_SYSCALL_TABLE_ID);
+    extracted_syscalls
+        .stack_argument_counts
+        .iter_mut()
+        .for_each(|argument_count| argument_count.0 |= WIN32K_SYSCALL_TABLE_ID);
+    extracted_syscalls
+        .unresolved_syscalls
+        .iter_mut()
+        .for_each(|syscall_id| *syscall_id |= WIN32K_SYSCALL_TABLE_ID);
+    extracted_syscalls
+        .implementations
+        .iter_mut()
+        .for_each(|implementation| implementation.0 |= WIN32K_SYSCALL_TABLE_ID);
+    extracted_syscalls
+        .filters
+        .iter_mut()
+        .flat_map(|(_, filter)| filter.iter_mut())
+        .for_each(|syscall_filter| syscall_filter.0 |= WIN32K_SYSCALL_TABLE_ID);
+
+    Ok(extracted_syscalls)
 }
 
 /// Find a service table start offset and size given its
@@ -110,16 +222,15 @@ fn find_service_table(
     Err(WinDiffError::SystemServiceTableNotFoundError)
 }
 
-/// Extract (and symbolize) syscall list from a service table
-fn extract_syscalls_from_service_table(
+/// Extract the RVAs of the routines referenced by a service table
+fn extract_service_table_entries(
     pe: &pe::PE<'_>,
     pe_data: &[u8],
-    symbols: &BTreeMap<u32, String>,
     service_table_info: &(u32, u32),
-) -> Result<Vec<(u32, String)>> {
+) -> Result<Vec<(u32, u32)>> {
     // Determine the service table's content (RVAs vs VAs)
     let service_table_contains_rva: bool =
-        does_service_table_contain_rva(pe.image_base, pe_data, symbols, service_table_info);
+        does_service_table_contain_rva(pe, pe_data, service_table_info)?;
 
     // Determine the size of elements in the service table
     let size_of_table_element = if service_table_contains_rva {
@@ -139,61 +250,196 @@ fn extract_syscalls_from_service_table(
         if service_table_contains_rva {
             // This service table contains relative virtual addresses
             let syscall_impl_rva = u32::from_le_bytes(syscall_impl_offset_bytes.try_into()?);
-            let symbol_name = symbols
-                .get(&syscall_impl_rva)
-                .ok_or_else(|| WinDiffError::SystemServiceTableParsingError)?;
-            result.push((syscall_id, symbol_name.clone()));
+            result.push((syscall_id, syscall_impl_rva));
         } else {
             // This service table contains virtual addresses
             let syscall_impl_va = u64::from_le_bytes(syscall_impl_offset_bytes.try_into()?);
-            let syscall_impl_rva = (syscall_impl_va - pe.image_base) as u32;
-            let symbol_name = symbols
-                .get(&syscall_impl_rva)
-                .ok_or_else(|| WinDiffError::SystemServiceTableParsingError)?;
-            result.push((syscall_id, symbol_name.clone()));
+            let syscall_impl_rva = syscall_impl_va
+                .checked_sub(pe.image_base)
+                .ok_or_else(|| WinDiffError::SystemServiceTableParsingError)?
+                as u32;
+            result.push((syscall_id, syscall_impl_rva));
         }
     }
 
     Ok(result)
 }
 
+/// Symbolize service table entries.
+///
+/// Entries that aren't found in the PDB's symbols fall back to an export with
+/// the same RVA, the nearest preceding symbol or a `sub_<rva>` placeholder,
+/// and are reported as unresolved.
+fn symbolize_service_table_entries(
+    pe: &pe::PE<'_>,
+    symbols: &BTreeMap<u32, String>,
+    service_table_entries: &[(u32, u32)],
+) -> (Vec<(u32, String)>, Vec<u32>) {
+    let mut syscalls = Vec::with_capacity(service_table_entries.len());
+    let mut unresolved_syscalls = vec![];
+    for (syscall_id, syscall_impl_rva) in service_table_entries {
+        let symbol_name = if let Some(symbol_name) = symbols.get(syscall_impl_rva) {
+            symbol_name.clone()
+        } else {
+            log::debug!(
+                "No symbol found for syscall {:#x} (RVA: {:#x})",
+                syscall_id,
+                syscall_impl_rva
+            );
+            unresolved_syscalls.push(*syscall_id);
+            symbolize_unknown_rva(pe, symbols, *syscall_impl_rva)
+        };
+        syscalls.push((*syscall_id, symbol_name));
+    }
+
+    (syscalls, unresolved_syscalls)
+}
+
+/// Determine which syscalls are allowed by the filtered variants of a service
+/// table (e.g., 'W32pServiceTableFilter' for 'W32pServiceTable'), which are
+/// used instead of the regular table for processes with syscall restrictions.
+///
+/// Filtered tables are found by looking for symbols prefixed with the regular
+/// table's name (with a matching limit symbol), and a syscall is considered
+/// allowed if its entry points to the same routine in both tables. Filter
+/// bitmaps aren't parsed, so filter sets only described by a bitmap are not
+/// reported.
+fn extract_service_table_filters(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    service_table_name: &str,
+    service_table_size_name: &str,
+    service_table_entries: &[(u32, u32)],
+) -> Vec<SyscallFilter> {
+    let filter_suffixes: BTreeSet<&str> = symbols
+        .values()
+        .filter_map(|symbol_name| symbol_name.strip_prefix(service_table_name))
+        .filter(|suffix| !suffix.is_empty())
+        .collect();
+
+    let mut result = vec![];
+    for filter_suffix in filter_suffixes {
+        let filter_table_name = format!("{}{}", service_table_name, filter_suffix);
+        let filter_table_size_name = format!("{}{}", service_table_size_name, filter_suffix);
+        let filter_table_info = match find_service_table(
+            pe,
+            pe_data,
+            symbols,
+            &filter_table_name,
+            &filter_table_size_name,
+        ) {
+            Ok(filter_table_info) => filter_table_info,
+            // Not a filtered service table
+            Err(WinDiffError::SystemServiceTableNotFoundError) => continue,
+            Err(err) => {
+                log::warn!(
+                    "Failed to find filtered service table '{}': {}",
+                    filter_table_name,
+                    err
+                );
+                continue;
+            }
+        };
+        log::trace!("Found filtered service table '{}'", filter_table_name);
+
+        let filter_table_entries =
+            match extract_service_table_entries(pe, pe_data, &filter_table_info) {
+                Ok(filter_table_entries) => filter_table_entries,
+                Err(err) => {
+                    log::warn!(
+                        "Failed to parse filtered service table '{}': {}",
+                        filter_table_name,
+                        err
+                    );
+                    continue;
+                }
+            };
+        let filter = service_table_entries
+            .iter()
+            .map(|(syscall_id, syscall_impl_rva)| {
+                let allowed = filter_table_entries
+                    .get(*syscall_id as usize)
+                    .map(|(_, filtered_impl_rva)| filtered_impl_rva == syscall_impl_rva)
+                    .unwrap_or_default();
+                (*syscall_id, allowed)
+            })
+            .collect();
+        result.push((filter_table_name, filter));
+    }
+
+    result
+}
+
+/// Extract the number of stack arguments of each syscall from an argument
+/// table, given the table's symbol name.
+///
+/// Argument tables are byte arrays that store the size of the arguments passed
+/// on the stack for each entry of the associated service table, with 4 bytes
+/// per argument. Arguments passed in registers aren't described by these
+/// tables. Returns an empty list if the table cannot be found.
+fn extract_stack_argument_counts(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+    argument_table_name: &str,
+    service_limit: u32,
+) -> Result<Vec<(u32, u32)>> {
+    let argument_table_rva = match symbols
+        .iter()
+        .find(|(_, symbol_name)| *symbol_name == argument_table_name)
+    {
+        Some((symbol_offset, _)) => *symbol_offset,
+        None => {
+            log::debug!("Argument table '{}' not found", argument_table_name);
+            return Ok(vec![]);
+        }
+    };
+    let argument_table_offset = rva_to_offset(argument_table_rva as usize, pe)?;
+    let argument_table = pe_data
+        .get(argument_table_offset..argument_table_offset + service_limit as usize)
+        .ok_or(WinDiffError::OutOfBoundsRead(argument_table_offset))?;
+
+    Ok(argument_table
+        .iter()
+        .enumerate()
+        .map(|(syscall_id, argument_size)| (syscall_id as u32, u32::from(*argument_size) / 4))
+        .collect())
+}
+
 /// Determine if the given service table seems to contain RVAs or VAs
 ///
-/// The logic is pretty basic and simply checks if the first element seems to be a virtual address.
+/// The first entries are read as VAs: the table is considered to contain VAs
+/// if they all point to code within the image. Pairs of RVAs read as a single
+/// VA can't pass this check, as the second RVA would have to match the upper
+/// half of the image base.
 fn does_service_table_contain_rva(
-    image_base: u64,
+    pe: &pe::PE<'_>,
     pe_data: &[u8],
-    symbols: &BTreeMap<u32, String>,
     service_table_info: &(u32, u32),
-) -> bool {
-    let current_offset_in_table = service_table_info.0 as usize;
-    let syscall_impl_offset_bytes =
-        &pe_data[current_offset_in_table..current_offset_in_table + std::mem::size_of::<u64>()];
-    let syscall_impl_va = u64::from_le_bytes(
-        syscall_impl_offset_bytes
-            .try_into()
-            .expect("invalid array (this is a bug)"),
-    );
-    let syscall_impl_rva = syscall_impl_va.saturating_sub(image_base) as u32;
-
-    syscall_impl_rva == 0 || symbols.get(&syscall_impl_rva).is_none()
-}
+) -> Result<bool> {
+    let size_of_image = pe
+        .header
+        .optional_header
+        .ok_or(WinDiffError::MissingExecutableOptionalHeader)?
+        .windows_fields
+        .size_of_image;
+    let image_range = pe.image_base..pe.image_base + u64::from(size_of_image);
+
+    let sampled_entry_count = service_table_info.1.min(SERVICE_TABLE_SAMPLE_SIZE);
+    for syscall_id in 0..sampled_entry_count {
+        let syscall_impl_va = read_u64(
+            pe_data,
+            (service_table_info.0 + std::mem::size_of::<u64>() as u32 * syscall_id) as usize,
+        )?;
+        if !image_range.contains(&syscall_impl_va)
+            || !is_code_rva(pe, (syscall_impl_va - pe.image_base) as u32)
+        {
+            return Ok(true);
+        }
+    }
 
-/// Convert an RVA to a file offset
-fn rva_to_offset(rva: usize, pe: &pe::PE<'_>) -> Result<usize> {
-    let mut parse_options = pe::options::ParseOptions::default();
-    parse_options.resolve_rva = true;
-    pe::utils::find_offset(
-        rva,
-        &pe.sections,
-        pe.header
-            .optional_header
-            .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)?
-            .windows_fields
-            .file_alignment,
-        &parse_options,
-    )
-    .ok_or_else(|| WinDiffError::MissingExecutableOptionalHeader)
+    Ok(sampled_entry_count == 0)
 }
 
 /// Handle user-mode binaries (i.e., ntdll.dll and win32u.dll)
@@ -243,6 +489,58 @@ fn extract_syscalls_from_user_binaries(
     Ok(syscalls)
 }
 
+/// Extract syscalls from the ARM64EC view of ARM64X user-mode binaries (i.e.,
+/// ntdll.dll and win32u.dll on arm64).
+///
+/// The EC view exports its own stubs, which may either be ARM64EC or x64 code
+/// depending on the code range they're located in. Returns an empty list for
+/// non-hybrid PEs.
+pub fn extract_ec_syscalls(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(u32, String)>> {
+    if pe.header.coff_header.machine != OSArchitecture::Arm64.to_machine_type() {
+        return Ok(vec![]);
+    }
+    let hybrid_metadata = match HybridMetadata::parse(pe, pe_data)? {
+        Some(hybrid_metadata) => hybrid_metadata,
+        None => return Ok(vec![]),
+    };
+    log::trace!("Extracting ARM64EC syscalls");
+
+    let syscalls = extract_ec_exports(pe, pe_data)?
+        .into_iter()
+        .filter_map(|(export_name, export_rva)| {
+            let export_offset = rva_to_offset(export_rva as usize, pe).ok()?;
+            let export_data = pe_data.get(export_offset..)?;
+            match hybrid_metadata.code_kind(export_rva)? {
+                HybridCodeKind::Amd64 => extract_ec_syscall_id_amd64(export_name, export_data),
+                HybridCodeKind::Arm64 | HybridCodeKind::Arm64EC => {
+                    extract_user_syscall_id_arm64(export_name, export_data)
+                }
+            }
+        })
+        .collect();
+
+    Ok(syscalls)
+}
+
+fn extract_ec_syscall_id_amd64(export_name: String, export_data: &[u8]) -> Option<(u32, String)> {
+    // x64 stubs found in ARM64X binaries start like regular AMD64 stubs but
+    // don't necessarily issue the `syscall` instruction at the same offset:
+    // mov r10, rcx
+    // mov eax, IMM32 ; <- syscall id
+    const SYSCALL_STUB_ENTRY_BYTES: [u8; 4] = [0x4c, 0x8b, 0xd1, 0xb8];
+
+    if export_data.get(..SYSCALL_STUB_ENTRY_BYTES.len())? == SYSCALL_STUB_ENTRY_BYTES {
+        let syscall_id_offset = SYSCALL_STUB_ENTRY_BYTES.len();
+        let syscall_id_bytes =
+            export_data.get(syscall_id_offset..syscall_id_offset + std::mem::size_of::<u32>())?;
+        let syscall_id = u32::from_le_bytes(syscall_id_bytes.try_into().ok()?);
+
+        Some((syscall_id, export_name))
+    } else {
+        None
+    }
+}
+
 fn extract_user_syscall_id_amd64(export_name: String, export_data: &[u8]) -> Option<(u32, String)> {
     // We want to match the following stub:
     // mov r10, rcx
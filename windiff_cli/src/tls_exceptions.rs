@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+
+use goblin::pe::{
+    self,
+    exception::{UnwindHandler, ARM64_PDATA_PACKED_UNWIND_FRAGMENT, ARM64_PDATA_REF_TO_FULL_XDATA},
+};
+use serde::Serialize;
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    imports::{import_address_table, resolve_import_thunk},
+    pe_utils::symbolize_unknown_rva,
+};
+
+/// Summary of the exception directory (`.pdata`)
+#[derive(Serialize, Debug, Default)]
+pub struct ExceptionDirectory {
+    /// Number of `RUNTIME_FUNCTION` entries
+    pub function_count: u32,
+    /// Number of entries whose unwind information is chained to another
+    /// entry's (AMD64 only)
+    pub chained_function_count: u32,
+    /// Number of entries that describe a fragment of a function with packed
+    /// unwind data (ARM64 only)
+    pub packed_fragment_count: u32,
+    /// Functions with an exception or termination handler (function name ->
+    /// handler name)
+    pub handlers: BTreeMap<String, String>,
+}
+
+/// Extract the TLS callbacks of a given PE, in the order they're called by the
+/// loader.
+pub fn extract_tls_callbacks(pe: &pe::PE<'_>, symbols: &BTreeMap<u32, String>) -> Vec<String> {
+    let tls_data = match &pe.tls_data {
+        Some(tls_data) => tls_data,
+        None => return vec![],
+    };
+    log::trace!("Extracting TLS callbacks");
+
+    tls_data
+        .callbacks
+        .iter()
+        .map(|callback_va| {
+            let callback_rva = callback_va.wrapping_sub(pe.image_base) as u32;
+            symbolize_rva(pe, symbols, callback_rva)
+        })
+        .collect()
+}
+
+/// Summarize the exception directory of a given PE (for AMD64 and ARM64
+/// binaries, which describe all their non-leaf functions in `.pdata`).
+pub fn extract_exception_directory(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Option<ExceptionDirectory>> {
+    let exception_data = match &pe.exception_data {
+        Some(exception_data) => exception_data,
+        None => return Ok(None),
+    };
+    log::trace!("Summarizing exception directory");
+
+    let import_address_table = import_address_table(pe);
+    let symbolize_handler = |handler_rva: u32| {
+        resolve_import_thunk(pe, pe_data, &import_address_table, handler_rva)
+            .map(|imported_function| imported_function.function.clone())
+            .unwrap_or_else(|| symbolize_rva(pe, symbols, handler_rva))
+    };
+
+    let mut result = ExceptionDirectory::default();
+    if pe.header.coff_header.machine == OSArchitecture::Amd64.to_machine_type() {
+        for function in exception_data.functions() {
+            let function = match function {
+                Ok(function) => function,
+                Err(err) => {
+                    log::debug!("Stopping at malformed runtime function: {}", err);
+                    break;
+                }
+            };
+            result.function_count += 1;
+            let unwind_info = match exception_data.get_unwind_info(function, &pe.sections) {
+                Ok(unwind_info) => unwind_info,
+                Err(err) => {
+                    log::debug!(
+                        "Failed to parse unwind information of function {:#x}: {}",
+                        function.begin_address,
+                        err
+                    );
+                    continue;
+                }
+            };
+
+            if unwind_info.chained_info.is_some() {
+                result.chained_function_count += 1;
+            }
+            if let Some(
+                UnwindHandler::ExceptionHandler(handler_rva, _)
+                | UnwindHandler::TerminationHandler(handler_rva, _),
+            ) = unwind_info.handler
+            {
+                result.handlers.insert(
+                    symbolize_rva(pe, symbols, function.begin_address),
+                    symbolize_handler(handler_rva),
+                );
+            }
+        }
+    } else if pe.header.coff_header.machine == OSArchitecture::Arm64.to_machine_type() {
+        for function in exception_data.functions_arm64() {
+            let function = match function {
+                Ok(function) => function,
+                Err(err) => {
+                    log::debug!("Stopping at malformed runtime function: {}", err);
+                    break;
+                }
+            };
+            result.function_count += 1;
+            match function.flag() {
+                ARM64_PDATA_REF_TO_FULL_XDATA => {}
+                // Packed unwind data can't reference a handler
+                ARM64_PDATA_PACKED_UNWIND_FRAGMENT => {
+                    result.packed_fragment_count += 1;
+                    continue;
+                }
+                _ => continue,
+            }
+
+            let unwind_info = match exception_data.get_unwind_info_arm64(function, &pe.sections) {
+                Some(Ok(unwind_info)) => unwind_info,
+                Some(Err(err)) => {
+                    log::debug!(
+                        "Failed to parse unwind information of function {:#x}: {}",
+                        function.begin_address,
+                        err
+                    );
+                    continue;
+                }
+                None => continue,
+            };
+            if let Some(exception_handler) = unwind_info.exception_handler {
+                result.handlers.insert(
+                    symbolize_rva(pe, symbols, function.begin_address),
+                    symbolize_handler(exception_handler.rva),
+                );
+            }
+        }
+    } else {
+        log::debug!("Unsupported architecture, exception directory won't be summarized");
+        return Ok(None);
+    }
+
+    Ok(Some(result))
+}
+
+/// Get the name of the symbol located at the given RVA, or generate one
+fn symbolize_rva(pe: &pe::PE<'_>, symbols: &BTreeMap<u32, String>, rva: u32) -> String {
+    symbols
+        .get(&rva)
+        .cloned()
+        .unwrap_or_else(|| symbolize_unknown_rva(pe, symbols, rva))
+}
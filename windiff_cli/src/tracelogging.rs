@@ -0,0 +1,500 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use iced_x86::{Decoder, DecoderOptions};
+use serde::Serialize;
+
+use crate::{
+    configuration::OSArchitecture,
+    error::Result,
+    pe_utils::{format_guid, read_bytes, read_pointer_as_rva, read_u16, read_u64},
+};
+
+/// "ETW0"
+const TRACELOGGING_METADATA_SIGNATURE: &[u8] = b"ETW0";
+const TRACELOGGING_METADATA_MAGIC: u64 = 0xbb8a_052b_8804_0e86;
+/// Name used for events that couldn't be attributed to a provider
+const UNKNOWN_PROVIDER_NAME: &str = "<unknown>";
+/// Maximum distance (in instructions) between references to an event and to
+/// the provider it's written to
+const MAX_EVENT_PROVIDER_DISTANCE: usize = 32;
+
+// Blob types (`_TlgBlobType`)
+const TLG_BLOB_NONE: u8 = 0;
+const TLG_BLOB_EVENT3: u8 = 3;
+const TLG_BLOB_PROVIDER3: u8 = 4;
+const TLG_BLOB_EVENT2: u8 = 5;
+const TLG_BLOB_EVENT4: u8 = 6;
+
+// Field count flags (`_TlgInFlag*`)
+const TLG_IN_COUNT_MASK: u8 = 0x60;
+const TLG_IN_CONSTANT_COUNT: u8 = 0x20;
+const TLG_IN_VARIABLE_COUNT: u8 = 0x40;
+const TLG_IN_CUSTOM: u8 = 0x60;
+const TLG_IN_CHAIN: u8 = 0x80;
+
+/// TraceLogging provider, with the events written to it
+#[derive(Serialize, Debug, Default)]
+pub struct TraceLoggingProvider {
+    pub guid: Option<String>,
+    pub events: BTreeMap<String, TraceLoggingEvent>,
+}
+
+/// TraceLogging event metadata
+#[derive(Serialize, Debug, Clone)]
+pub struct TraceLoggingEvent {
+    pub channel: u8,
+    pub level: u8,
+    pub opcode: u8,
+    pub keyword: String,
+    /// Fields, formatted as "name : type"
+    pub fields: Vec<String>,
+}
+
+/// Metadata blob found in the TraceLogging metadata section, with the RVA
+/// range it occupies
+enum MetadataBlob {
+    Provider {
+        name: String,
+        guid: String,
+        /// RVA of the `RemainingSize` field, which provider handles point to
+        metadata_rva: u32,
+    },
+    Event {
+        name: String,
+        event: TraceLoggingEvent,
+        rva_range: (u32, u32),
+    },
+}
+
+/// Extract the TraceLogging providers and events found in a given PE, as
+/// (provider name, provider) pairs. Returns an empty list if the PE doesn't
+/// contain TraceLogging metadata.
+pub fn extract_tracelogging(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+) -> Result<Vec<(String, TraceLoggingProvider)>> {
+    let (metadata_rva, section_data, metadata_offset) = match find_metadata_header(pe, pe_data) {
+        Some(metadata_location) => metadata_location,
+        None => return Ok(vec![]),
+    };
+    log::trace!("Extracting TraceLogging metadata");
+
+    let mut providers = BTreeMap::new();
+    let mut events = vec![];
+    for blob in parse_metadata_blobs(section_data, metadata_rva, metadata_offset)? {
+        match blob {
+            MetadataBlob::Provider {
+                name,
+                guid,
+                metadata_rva,
+            } => {
+                providers.insert(metadata_rva, (name, guid));
+            }
+            MetadataBlob::Event {
+                name,
+                event,
+                rva_range,
+            } => events.push((name, event, rva_range)),
+        }
+    }
+
+    // Events don't reference their provider, so we have to rely on the code
+    // that writes them when there's more than one provider
+    let event_providers = if providers.len() == 1 {
+        let provider_metadata_rva = providers.keys().next().copied();
+        events.iter().map(|_| provider_metadata_rva).collect()
+    } else {
+        attribute_events_to_providers(pe, pe_data, &providers, &events)?
+    };
+
+    let mut result: BTreeMap<String, TraceLoggingProvider> = providers
+        .values()
+        .map(|(name, guid)| {
+            (
+                name.clone(),
+                TraceLoggingProvider {
+                    guid: Some(guid.clone()),
+                    events: BTreeMap::new(),
+                },
+            )
+        })
+        .collect();
+    for ((event_name, event, _), provider_metadata_rva) in events.into_iter().zip(event_providers) {
+        let provider_name = provider_metadata_rva
+            .and_then(|provider_metadata_rva| providers.get(&provider_metadata_rva))
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(UNKNOWN_PROVIDER_NAME);
+        result
+            .entry(provider_name.to_string())
+            .or_default()
+            .events
+            .insert(event_name, event);
+    }
+
+    Ok(result.into_iter().collect())
+}
+
+/// Find the `_TraceLoggingMetadata_t` header, and return its RVA, the data of
+/// the section that contains it and its offset in that section
+fn find_metadata_header<'d>(pe: &pe::PE<'_>, pe_data: &'d [u8]) -> Option<(u32, &'d [u8], usize)> {
+    for section in pe.sections.iter().filter(|section| {
+        section.characteristics & pe::section_table::IMAGE_SCN_CNT_INITIALIZED_DATA != 0
+            && section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE == 0
+    }) {
+        let section_offset = section.pointer_to_raw_data as usize;
+        let section_size = section.size_of_raw_data.min(section.virtual_size) as usize;
+        let section_data = match read_bytes(pe_data, section_offset, section_size) {
+            Ok(section_data) => section_data,
+            Err(err) => {
+                log::debug!(
+                    "Skipping truncated section {}: {}",
+                    section.name().unwrap_or_default(),
+                    err
+                );
+                continue;
+            }
+        };
+
+        for (header_offset, _) in section_data
+            .windows(TRACELOGGING_METADATA_SIGNATURE.len())
+            .enumerate()
+            .filter(|(_, bytes)| *bytes == TRACELOGGING_METADATA_SIGNATURE)
+        {
+            if read_u64(section_data, header_offset + 8).ok() == Some(TRACELOGGING_METADATA_MAGIC) {
+                return Some((
+                    section.virtual_address + header_offset as u32,
+                    section_data,
+                    header_offset,
+                ));
+            }
+        }
+    }
+
+    None
+}
+
+/// Parse the metadata blobs that follow the metadata header, up to the end of
+/// the section that contains them
+fn parse_metadata_blobs(
+    section_data: &[u8],
+    header_rva: u32,
+    header_offset: usize,
+) -> Result<Vec<MetadataBlob>> {
+    let header_size = read_u16(section_data, header_offset + 4)? as usize;
+
+    let mut result = vec![];
+    let mut blob_offset = header_offset + header_size;
+    while let Some(blob_type) = section_data.get(blob_offset).copied() {
+        let blob_rva = header_rva + (blob_offset - header_offset) as u32;
+        let blob_size = match blob_type {
+            // Padding between metadata sections
+            TLG_BLOB_NONE => 1,
+            TLG_BLOB_PROVIDER3 => {
+                let guid = format_guid(read_bytes(section_data, blob_offset + 1, 16)?.try_into()?);
+                let remaining_size = read_u16(section_data, blob_offset + 17)? as usize;
+                let mut reader = MetadataReader::new(read_bytes(
+                    section_data,
+                    blob_offset + 19,
+                    remaining_size.saturating_sub(2),
+                )?);
+                result.push(MetadataBlob::Provider {
+                    name: reader.read_string().unwrap_or_default(),
+                    guid,
+                    metadata_rva: blob_rva + 17,
+                });
+                17 + remaining_size
+            }
+            TLG_BLOB_EVENT2 | TLG_BLOB_EVENT3 | TLG_BLOB_EVENT4 => {
+                let fields = read_bytes(section_data, blob_offset + 1, 3)?;
+                // Event2 stores a task (after the opcode) instead of a channel
+                let (channel, level, opcode, keyword_offset) = if blob_type == TLG_BLOB_EVENT2 {
+                    (0, fields[0], fields[1], 5)
+                } else {
+                    (fields[0], fields[1], fields[2], 4)
+                };
+                let keyword = read_u64(section_data, blob_offset + keyword_offset)?;
+                let remaining_size =
+                    read_u16(section_data, blob_offset + keyword_offset + 8)? as usize;
+                let event_metadata = read_bytes(
+                    section_data,
+                    blob_offset + keyword_offset + 10,
+                    remaining_size.saturating_sub(2),
+                )?;
+                let blob_size = keyword_offset + 8 + remaining_size;
+
+                if let Some((name, fields)) = parse_event_metadata(event_metadata) {
+                    result.push(MetadataBlob::Event {
+                        name,
+                        event: TraceLoggingEvent {
+                            channel,
+                            level,
+                            opcode,
+                            keyword: format!("{:#x}", keyword),
+                            fields,
+                        },
+                        rva_range: (blob_rva, blob_rva + blob_size as u32),
+                    });
+                }
+                blob_size
+            }
+            // End marker and unknown blob types
+            _ => break,
+        };
+
+        blob_offset += blob_size.max(1);
+    }
+
+    Ok(result)
+}
+
+/// Parse an event's tags, name and fields
+fn parse_event_metadata(event_metadata: &[u8]) -> Option<(String, Vec<String>)> {
+    let mut reader = MetadataReader::new(event_metadata);
+    reader.skip_chained_bytes()?;
+    let event_name = reader.read_string()?;
+
+    let mut fields = vec![];
+    while !reader.is_empty() {
+        let field_name = reader.read_string()?;
+        let in_type = reader.read_u8()?;
+        if in_type & TLG_IN_CHAIN != 0 {
+            let out_type = reader.read_u8()?;
+            if out_type & TLG_IN_CHAIN != 0 {
+                reader.skip_chained_bytes()?;
+            }
+        }
+        let is_array = match in_type & TLG_IN_COUNT_MASK {
+            TLG_IN_CONSTANT_COUNT => {
+                let _value_count = reader.read_u16()?;
+                true
+            }
+            TLG_IN_VARIABLE_COUNT => true,
+            TLG_IN_CUSTOM => {
+                let type_info_size = reader.read_u16()?;
+                reader.skip(type_info_size as usize)?;
+                false
+            }
+            _ => false,
+        };
+
+        fields.push(format!(
+            "{} : {}{}",
+            field_name,
+            in_type_name(in_type & 0x1f),
+            if is_array { "[]" } else { "" }
+        ));
+    }
+
+    Some((event_name, fields))
+}
+
+/// Find which provider each event is written to, by looking for references to
+/// provider handles (`_tlgProvider_t`) close to references to the event's
+/// metadata in the code (AMD64 only).
+fn attribute_events_to_providers(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    providers: &BTreeMap<u32, (String, String)>,
+    events: &[(String, TraceLoggingEvent, (u32, u32))],
+) -> Result<Vec<Option<u32>>> {
+    if pe.header.coff_header.machine != OSArchitecture::Amd64.to_machine_type()
+        || providers.is_empty()
+    {
+        return Ok(vec![None; events.len()]);
+    }
+
+    // Provider handle RVA range -> provider metadata RVA
+    const SIZEOF_TLG_PROVIDER: u32 = 0x38;
+    let mut provider_handles = BTreeMap::new();
+    for section in pe
+        .sections
+        .iter()
+        .filter(|section| section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE == 0)
+    {
+        let section_size = section.size_of_raw_data.min(section.virtual_size);
+        for section_offset in (8..section_size.saturating_sub(8)).step_by(8) {
+            let pointer_offset = (section.pointer_to_raw_data + section_offset) as usize;
+            // _tlgProvider_t.ProviderMetadataPtr
+            if let Some(metadata_rva) = read_pointer_as_rva(pe, pe_data, pointer_offset)? {
+                if providers.contains_key(&metadata_rva) {
+                    let handle_rva = section.virtual_address + section_offset - 8;
+                    provider_handles
+                        .insert(handle_rva, (handle_rva + SIZEOF_TLG_PROVIDER, metadata_rva));
+                }
+            }
+        }
+    }
+    // Event metadata RVA range -> event index
+    let event_ranges: BTreeMap<u32, (u32, usize)> = events
+        .iter()
+        .enumerate()
+        .map(|(event_index, (_, _, (start_rva, end_rva)))| (*start_rva, (*end_rva, event_index)))
+        .collect();
+
+    // Collect references to events and providers, in code order
+    let mut references = vec![];
+    for section in pe
+        .sections
+        .iter()
+        .filter(|section| section.characteristics & pe::section_table::IMAGE_SCN_MEM_EXECUTE != 0)
+    {
+        let section_size = section.size_of_raw_data.min(section.virtual_size);
+        let code = read_bytes(
+            pe_data,
+            section.pointer_to_raw_data as usize,
+            section_size as usize,
+        )?;
+        let mut decoder = Decoder::with_ip(
+            64,
+            code,
+            pe.image_base + u64::from(section.virtual_address),
+            DecoderOptions::NONE,
+        );
+        for instruction in decoder.iter() {
+            if !instruction.is_ip_rel_memory_operand() {
+                continue;
+            }
+            let target_rva = instruction
+                .ip_rel_memory_address()
+                .wrapping_sub(pe.image_base) as u32;
+            let reference = if let Some(event_index) = find_in_ranges(&event_ranges, target_rva) {
+                EventReference::Event(event_index)
+            } else if let Some(metadata_rva) = find_in_ranges(&provider_handles, target_rva) {
+                EventReference::Provider(metadata_rva)
+            } else {
+                continue;
+            };
+            references.push(reference);
+        }
+    }
+
+    // Pick the closest provider reference for each event reference, and keep
+    // the provider that's picked most often for each event
+    let mut votes: Vec<BTreeMap<u32, usize>> = vec![BTreeMap::new(); events.len()];
+    for (reference_index, reference) in references.iter().enumerate() {
+        let event_index = match reference {
+            EventReference::Event(event_index) => *event_index,
+            EventReference::Provider(_) => continue,
+        };
+        let first_index = reference_index.saturating_sub(MAX_EVENT_PROVIDER_DISTANCE);
+        let last_index = (reference_index + MAX_EVENT_PROVIDER_DISTANCE).min(references.len() - 1);
+        let closest_provider = (first_index..=last_index)
+            .filter_map(|index| match references[index] {
+                EventReference::Provider(metadata_rva) => {
+                    Some((index.abs_diff(reference_index), metadata_rva))
+                }
+                EventReference::Event(_) => None,
+            })
+            .min();
+        if let Some((_, metadata_rva)) = closest_provider {
+            *votes[event_index].entry(metadata_rva).or_default() += 1;
+        }
+    }
+
+    Ok(votes
+        .into_iter()
+        .map(|event_votes| {
+            event_votes
+                .into_iter()
+                .max_by_key(|(_, vote_count)| *vote_count)
+                .map(|(metadata_rva, _)| metadata_rva)
+        })
+        .collect())
+}
+
+/// Reference to TraceLogging metadata found in the code
+enum EventReference {
+    Event(usize),
+    Provider(u32),
+}
+
+/// Find the value associated with the [start, end) range that contains `rva`
+fn find_in_ranges<T: Copy>(ranges: &BTreeMap<u32, (u32, T)>, rva: u32) -> Option<T> {
+    ranges
+        .range(..=rva)
+        .next_back()
+        .filter(|(_, (end_rva, _))| rva < *end_rva)
+        .map(|(_, (_, value))| *value)
+}
+
+/// Get the name of a `TlgIn_t` value
+pub fn in_type_name(in_type: u8) -> &'static str {
+    match in_type {
+        0 => "NULL",
+        1 => "UNICODESTRING",
+        2 => "ANSISTRING",
+        3 => "INT8",
+        4 => "UINT8",
+        5 => "INT16",
+        6 => "UINT16",
+        7 => "INT32",
+        8 => "UINT32",
+        9 => "INT64",
+        10 => "UINT64",
+        11 => "FLOAT",
+        12 => "DOUBLE",
+        13 => "BOOL32",
+        14 => "BINARY",
+        15 => "GUID",
+        16 => "POINTER",
+        17 => "FILETIME",
+        18 => "SYSTEMTIME",
+        19 => "SID",
+        20 => "HEXINT32",
+        21 => "HEXINT64",
+        22 => "COUNTEDSTRING",
+        23 => "COUNTEDANSISTRING",
+        24 => "STRUCT",
+        25 => "COUNTEDBINARY",
+        _ => "UNKNOWN",
+    }
+}
+
+/// Cursor over TraceLogging metadata
+struct MetadataReader<'d> {
+    data: &'d [u8],
+    position: usize,
+}
+
+impl<'d> MetadataReader<'d> {
+    fn new(data: &'d [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn read_u8(&mut self) -> Option<u8> {
+        let value = *self.data.get(self.position)?;
+        self.position += 1;
+        Some(value)
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        let value = self.data.get(self.position..self.position + 2)?;
+        self.position += 2;
+        Some(u16::from_le_bytes([value[0], value[1]]))
+    }
+
+    /// Read a NUL-terminated UTF-8 string
+    fn read_string(&mut self) -> Option<String> {
+        let remaining_data = self.data.get(self.position..)?;
+        let string_length = remaining_data.iter().position(|c| *c == 0)?;
+        self.position += string_length + 1;
+        Some(String::from_utf8_lossy(&remaining_data[..string_length]).to_string())
+    }
+
+    /// Skip a sequence of bytes terminated by a byte whose high bit is unset
+    /// (e.g., tags)
+    fn skip_chained_bytes(&mut self) -> Option<()> {
+        while self.read_u8()? & TLG_IN_CHAIN != 0 {}
+        Some(())
+    }
+
+    fn skip(&mut self, size: usize) -> Option<()> {
+        self.position = self.position.checked_add(size)?;
+        (self.position <= self.data.len()).then_some(())
+    }
+}
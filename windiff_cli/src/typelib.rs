@@ -0,0 +1,408 @@
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    pe_utils::{format_guid, read_bytes, read_u16, read_u32},
+    resources::{find_resources, ResourceId},
+};
+
+const TYPELIB_RESOURCE_TYPE: &str = "TYPELIB";
+/// "MSFT"
+const MSFT_MAGIC: u32 = 0x5446_534d;
+const SIZEOF_MSFT_HEADER: usize = 0x54;
+const SIZEOF_MSFT_SEGMENT: usize = 16;
+const SIZEOF_MSFT_TYPEINFO: usize = 0x64;
+const SIZEOF_MSFT_PARAMETER_INFO: usize = 12;
+const MSFT_HEADER_HAS_HELP_DLL: u32 = 0x100;
+const MSFT_IMPINFO_OFFSET_IS_GUID: u32 = 0x1_0000;
+/// Maximum nesting of type descriptions we're willing to follow
+const MAX_TYPE_DESCRIPTION_DEPTH: usize = 8;
+
+/// Indexes of the segments we use in the MSFT segment directory
+#[derive(Copy, Clone)]
+enum Segment {
+    TypeInfo = 0,
+    ImportInfo = 1,
+    References = 3,
+    Guids = 5,
+    Names = 7,
+    TypeDescriptions = 9,
+    ArrayDescriptions = 10,
+}
+const SEGMENT_COUNT: usize = 15;
+
+// https://learn.microsoft.com/en-us/windows/win32/api/oaidl/ne-oaidl-typekind
+const TKIND_INTERFACE: u32 = 3;
+const TKIND_DISPATCH: u32 = 4;
+const TKIND_COCLASS: u32 = 5;
+
+// https://learn.microsoft.com/en-us/windows/win32/api/wtypes/ne-wtypes-varenum
+const VT_TYPEMASK: u32 = 0xfff;
+const VT_PTR: u16 = 26;
+const VT_SAFEARRAY: u16 = 27;
+const VT_CARRAY: u16 = 28;
+const VT_USERDEFINED: u16 = 29;
+
+/// COM type (interface, dispinterface or coclass) described by a type library
+#[derive(Serialize, Debug)]
+pub struct ComType {
+    /// "interface", "dispinterface" or "coclass"
+    pub kind: String,
+    /// IID or CLSID
+    pub guid: Option<String>,
+    /// Base interface (for interfaces) or implemented interfaces (for coclasses)
+    pub base_types: Vec<String>,
+    /// Methods and properties, in declaration order
+    pub methods: Vec<ComMethod>,
+}
+
+/// Method (or property accessor) of a COM interface
+#[derive(Serialize, Debug)]
+pub struct ComMethod {
+    pub name: String,
+    /// DISPID (or MEMBERID for non-dispatch interfaces)
+    pub dispid: i32,
+    /// "method", "propget", "propput" or "propputref"
+    pub invoke_kind: String,
+    pub return_type: String,
+    /// Parameters, formatted as "[flags] type name"
+    pub parameters: Vec<String>,
+}
+
+/// Extract the COM types described by the type libraries (TYPELIB resources)
+/// embedded in a given PE, as ("Library.Type", type) pairs.
+pub fn extract_typelib(pe: &pe::PE<'_>, pe_data: &[u8]) -> Result<Vec<(String, ComType)>> {
+    let resources = find_resources(
+        pe,
+        pe_data,
+        &ResourceId::Name(TYPELIB_RESOURCE_TYPE.to_string()),
+    )?;
+
+    let mut result = vec![];
+    for resource in resources {
+        log::trace!(
+            "Parsing type library {:?} (language: {:#x})",
+            resource.name,
+            resource.language
+        );
+        match TypeLibParser::new(resource.data).and_then(|parser| parser.parse_types()) {
+            Ok(Some(com_types)) => result.extend(com_types),
+            Ok(None) => log::debug!("Unsupported type library format: {:?}", resource.name),
+            Err(err) => log::warn!("Failed to parse type library {:?}: {}", resource.name, err),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Parser for the MSFT type library format (produced by MIDL)
+struct TypeLibParser<'d> {
+    data: &'d [u8],
+    segment_offsets: [usize; SEGMENT_COUNT],
+    type_info_count: usize,
+}
+
+impl<'d> TypeLibParser<'d> {
+    fn new(data: &'d [u8]) -> Result<Self> {
+        let type_info_count = read_u32(data, 0x20)? as usize;
+        let mut segment_directory_offset = SIZEOF_MSFT_HEADER + 4 * type_info_count;
+        if read_u32(data, 0x14)? & MSFT_HEADER_HAS_HELP_DLL != 0 {
+            segment_directory_offset += 4;
+        }
+
+        let mut segment_offsets = [0; SEGMENT_COUNT];
+        for (i, segment_offset) in segment_offsets.iter_mut().enumerate() {
+            *segment_offset =
+                read_u32(data, segment_directory_offset + i * SIZEOF_MSFT_SEGMENT)? as usize;
+        }
+
+        Ok(Self {
+            data,
+            segment_offsets,
+            type_info_count,
+        })
+    }
+
+    /// Parse all the interfaces, dispinterfaces and coclasses of the library.
+    /// Returns `None` if the library doesn't use the MSFT format.
+    fn parse_types(&self) -> Result<Option<Vec<(String, ComType)>>> {
+        if read_u32(self.data, 0)? != MSFT_MAGIC {
+            return Ok(None);
+        }
+        let library_name = self.name(read_u32(self.data, 0x38)?)?;
+
+        let mut result = vec![];
+        for type_info_index in 0..self.type_info_count {
+            let type_info_offset =
+                self.segment_offset(Segment::TypeInfo) + type_info_index * SIZEOF_MSFT_TYPEINFO;
+            let kind = match read_u32(self.data, type_info_offset)? & 0xf {
+                TKIND_INTERFACE => "interface",
+                TKIND_DISPATCH => "dispinterface",
+                TKIND_COCLASS => "coclass",
+                _ => continue,
+            };
+            let type_name = self.name(read_u32(self.data, type_info_offset + 0x34)?)?;
+
+            let com_type = ComType {
+                kind: kind.to_string(),
+                guid: self.guid(read_u32(self.data, type_info_offset + 0x2c)?)?,
+                base_types: self.parse_base_types(type_info_offset)?,
+                methods: self.parse_methods(type_info_offset)?,
+            };
+            result.push((format!("{}.{}", library_name, type_name), com_type));
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Parse the base interface of an interface or the interfaces implemented
+    /// by a coclass
+    fn parse_base_types(&self, type_info_offset: usize) -> Result<Vec<String>> {
+        let type_kind = read_u32(self.data, type_info_offset)? & 0xf;
+        let implemented_type_count = read_u16(self.data, type_info_offset + 0x4c)?;
+        // MSFT_TypeInfoBase.datatype1
+        let data_type = read_u32(self.data, type_info_offset + 0x54)?;
+        if implemented_type_count == 0 || data_type == u32::MAX {
+            return Ok(vec![]);
+        }
+
+        match type_kind {
+            TKIND_INTERFACE => Ok(vec![self.reference_name(data_type)?]),
+            TKIND_COCLASS => {
+                // Walk the list of MSFT_RefRecord
+                let mut result = vec![];
+                let mut reference_offset = data_type;
+                while reference_offset != u32::MAX && result.len() < implemented_type_count as usize
+                {
+                    let record_offset =
+                        self.segment_offset(Segment::References) + reference_offset as usize;
+                    result.push(self.reference_name(read_u32(self.data, record_offset)?)?);
+                    reference_offset = read_u32(self.data, record_offset + 12)?;
+                }
+                Ok(result)
+            }
+            _ => Ok(vec![]),
+        }
+    }
+
+    /// Parse the function records of a type info
+    fn parse_methods(&self, type_info_offset: usize) -> Result<Vec<ComMethod>> {
+        // MSFT_TypeInfoBase.cElement
+        let function_count = read_u16(self.data, type_info_offset + 0x18)? as usize;
+        let variable_count = read_u16(self.data, type_info_offset + 0x1a)? as usize;
+        if function_count == 0 {
+            return Ok(vec![]);
+        }
+        // MSFT_TypeInfoBase.memoffset
+        let members_offset = read_u32(self.data, type_info_offset + 4)? as usize;
+        let records_size = read_u32(self.data, members_offset)? as usize;
+        // Member IDs and names are stored after the records
+        let member_ids_offset = members_offset + records_size + 4;
+        let member_names_offset = member_ids_offset + 4 * (function_count + variable_count);
+
+        let mut result = Vec::with_capacity(function_count);
+        let mut record_offset = members_offset + 4;
+        for function_index in 0..function_count {
+            // MSFT_FuncRecord
+            let record_size = read_u16(self.data, record_offset)? as usize;
+            let return_type = read_u32(self.data, record_offset + 4)?;
+            let flags = read_u32(self.data, record_offset + 16)?;
+            let parameter_count = read_u16(self.data, record_offset + 20)? as usize;
+
+            let invoke_kind = match (flags >> 3) & 0xf {
+                2 => "propget",
+                4 => "propput",
+                8 => "propputref",
+                _ => "method",
+            };
+
+            // MSFT_ParameterInfo are stored at the end of the record
+            let parameters_offset = (record_offset + record_size)
+                .checked_sub(parameter_count * SIZEOF_MSFT_PARAMETER_INFO)
+                .unwrap_or(record_offset);
+            let mut parameters = Vec::with_capacity(parameter_count);
+            for parameter_index in 0..parameter_count {
+                let parameter_offset =
+                    parameters_offset + parameter_index * SIZEOF_MSFT_PARAMETER_INFO;
+                let parameter_type = self.type_name(read_u32(self.data, parameter_offset)?, 0)?;
+                let parameter_name = self.name(read_u32(self.data, parameter_offset + 4)?)?;
+                let parameter_flags =
+                    format_parameter_flags(read_u32(self.data, parameter_offset + 8)?);
+                parameters.push(
+                    format!("{} {} {}", parameter_flags, parameter_type, parameter_name)
+                        .trim()
+                        .to_string(),
+                );
+            }
+
+            result.push(ComMethod {
+                name: self.name(read_u32(
+                    self.data,
+                    member_names_offset + 4 * function_index,
+                )?)?,
+                dispid: read_u32(self.data, member_ids_offset + 4 * function_index)? as i32,
+                invoke_kind: invoke_kind.to_string(),
+                return_type: self.type_name(return_type, 0)?,
+                parameters,
+            });
+
+            record_offset += record_size;
+        }
+
+        Ok(result)
+    }
+
+    /// Format a type description (either a base type or an offset in the type
+    /// description table)
+    fn type_name(&self, data_type: u32, depth: usize) -> Result<String> {
+        if (data_type as i32) < 0 {
+            return Ok(variant_type_name(data_type & VT_TYPEMASK));
+        }
+        if depth >= MAX_TYPE_DESCRIPTION_DEPTH {
+            return Ok("...".to_string());
+        }
+
+        let description_offset =
+            self.segment_offset(Segment::TypeDescriptions) + data_type as usize;
+        let variant_type = read_u16(self.data, description_offset)?;
+        let inner_type = read_u32(self.data, description_offset + 4)?;
+        match variant_type {
+            VT_PTR => Ok(format!("{}*", self.type_name(inner_type, depth + 1)?)),
+            VT_SAFEARRAY => Ok(format!(
+                "SAFEARRAY({})",
+                self.type_name(inner_type, depth + 1)?
+            )),
+            VT_USERDEFINED => self.reference_name(inner_type),
+            VT_CARRAY => {
+                let array_offset =
+                    self.segment_offset(Segment::ArrayDescriptions) + inner_type as usize;
+                let element_type = self.type_name(read_u32(self.data, array_offset)?, depth + 1)?;
+                let dimension_count = read_u16(self.data, array_offset + 4)? as usize;
+                let mut result = element_type;
+                for dimension in 0..dimension_count {
+                    let element_count = read_u32(self.data, array_offset + 8 + 8 * dimension)?;
+                    result += &format!("[{}]", element_count);
+                }
+                Ok(result)
+            }
+            _ => Ok(variant_type_name(u32::from(variant_type) & VT_TYPEMASK)),
+        }
+    }
+
+    /// Resolve the name of a type referenced by an `HREFTYPE`
+    fn reference_name(&self, reference_type: u32) -> Result<String> {
+        if reference_type & 3 == 0 {
+            // Type info from this library
+            let type_info_offset = self.segment_offset(Segment::TypeInfo) + reference_type as usize;
+            self.name(read_u32(self.data, type_info_offset + 0x34)?)
+        } else {
+            // Type info imported from another library, which we can only
+            // identify by GUID
+            let import_offset =
+                self.segment_offset(Segment::ImportInfo) + (reference_type & !3) as usize;
+            let import_flags = read_u32(self.data, import_offset)?;
+            if import_flags & MSFT_IMPINFO_OFFSET_IS_GUID == 0 {
+                return Ok("<imported>".to_string());
+            }
+            let guid = self
+                .guid(read_u32(self.data, import_offset + 8)?)?
+                .unwrap_or_default();
+            Ok(match guid.as_str() {
+                "00000000-0000-0000-c000-000000000046" => "IUnknown".to_string(),
+                "00020400-0000-0000-c000-000000000046" => "IDispatch".to_string(),
+                _ => format!("{{{}}}", guid),
+            })
+        }
+    }
+
+    /// Read an entry of the name table
+    fn name(&self, name_offset: u32) -> Result<String> {
+        if name_offset == u32::MAX {
+            return Ok(String::default());
+        }
+        // MSFT_NameIntro
+        let name_offset = self.segment_offset(Segment::Names) + name_offset as usize;
+        let name_length = read_u32(self.data, name_offset + 8)? & 0xff;
+        let name = read_bytes(self.data, name_offset + 12, name_length as usize)?;
+
+        Ok(String::from_utf8_lossy(name).to_string())
+    }
+
+    /// Read an entry of the GUID table
+    fn guid(&self, guid_offset: u32) -> Result<Option<String>> {
+        if guid_offset == u32::MAX {
+            return Ok(None);
+        }
+        let guid_offset = self.segment_offset(Segment::Guids) + guid_offset as usize;
+
+        Ok(Some(format_guid(
+            read_bytes(self.data, guid_offset, 16)?.try_into()?,
+        )))
+    }
+
+    fn segment_offset(&self, segment: Segment) -> usize {
+        self.segment_offsets[segment as usize]
+    }
+}
+
+/// Format a `PARAMFLAG_*` bitfield in IDL syntax (e.g., "[out, retval]")
+fn format_parameter_flags(flags: u32) -> String {
+    const PARAMETER_FLAGS: [(u32, &str); 5] = [
+        (0x1, "in"),
+        (0x2, "out"),
+        (0x4, "lcid"),
+        (0x8, "retval"),
+        (0x10, "optional"),
+    ];
+
+    let flags: Vec<&str> = PARAMETER_FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, flag_name)| *flag_name)
+        .collect();
+    if flags.is_empty() {
+        String::default()
+    } else {
+        format!("[{}]", flags.join(", "))
+    }
+}
+
+/// Get the IDL name of a `VARENUM` value
+fn variant_type_name(variant_type: u32) -> String {
+    match variant_type {
+        0 => "EMPTY",
+        1 => "NULL",
+        2 => "short",
+        3 => "long",
+        4 => "float",
+        5 => "double",
+        6 => "CURRENCY",
+        7 => "DATE",
+        8 => "BSTR",
+        9 => "IDispatch*",
+        10 => "SCODE",
+        11 => "VARIANT_BOOL",
+        12 => "VARIANT",
+        13 => "IUnknown*",
+        14 => "DECIMAL",
+        16 => "char",
+        17 => "unsigned char",
+        18 => "unsigned short",
+        19 => "unsigned long",
+        20 => "int64",
+        21 => "uint64",
+        22 => "int",
+        23 => "unsigned int",
+        24 => "void",
+        25 => "HRESULT",
+        30 => "LPSTR",
+        31 => "LPWSTR",
+        37 => "INT_PTR",
+        38 => "UINT_PTR",
+        64 => "FILETIME",
+        71 => "CLSID",
+        _ => return format!("VT_{}", variant_type),
+    }
+    .to_string()
+}
@@ -0,0 +1,78 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+
+use crate::{callbacks::extract_callbacks_from_table, error::Result};
+
+/// Prefix of decorated vftable names (e.g., "??_7CFoo@@6B@")
+const DECORATED_VFTABLE_PREFIX: &str = "??_7";
+/// Separator between the class and the base class in decorated vftable names
+const DECORATED_VFTABLE_SEPARATOR: &str = "@@6B";
+/// Suffix of undecorated vftable names (e.g., "CFoo::`vftable'")
+const UNDECORATED_VFTABLE_SUFFIX: &str = "::`vftable'";
+
+/// Extract the C++ virtual function tables defined in a given PE, as (class
+/// name, symbolized slots) pairs.
+pub fn extract_vtables(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, Vec<String>)>> {
+    log::trace!("Extracting vtables");
+
+    let mut result = vec![];
+    for (vtable_rva, symbol_name) in symbols {
+        let class_name = match parse_vtable_name(symbol_name) {
+            Some(class_name) => class_name,
+            None => continue,
+        };
+
+        match extract_callbacks_from_table(pe, pe_data, symbols, *vtable_rva) {
+            Ok(slots) => result.push((
+                class_name,
+                slots.into_iter().map(|(_, slot_name)| slot_name).collect(),
+            )),
+            Err(err) => log::debug!("Failed to read vtable '{}': {}", symbol_name, err),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Get the name of the class (and base class, for classes with multiple
+/// vftables) a vftable symbol belongs to, formatted as "Namespace::Class" or
+/// "Namespace::Class{for `Base'}"
+pub fn parse_vtable_name(symbol_name: &str) -> Option<String> {
+    // Undecorated names
+    let symbol_name = symbol_name.trim_start_matches("const ");
+    if let Some((class_name, base_name)) = symbol_name.split_once(UNDECORATED_VFTABLE_SUFFIX) {
+        return Some(format!("{}{}", class_name, base_name));
+    }
+
+    // Decorated names
+    let (class_name, base_name) = symbol_name
+        .strip_prefix(DECORATED_VFTABLE_PREFIX)?
+        .split_once(DECORATED_VFTABLE_SEPARATOR)?;
+    // Keep the decorated name of templates and other complex types
+    if class_name.contains(['?', '$']) || base_name.contains(['?', '$']) {
+        return Some(symbol_name.to_string());
+    }
+    let class_name = undecorate_qualified_name(class_name);
+    let base_name = base_name.trim_end_matches('@');
+
+    Some(if base_name.is_empty() {
+        class_name
+    } else {
+        format!(
+            "{}{{for `{}'}}",
+            class_name,
+            undecorate_qualified_name(base_name)
+        )
+    })
+}
+
+/// Convert a decorated qualified name (e.g., "CFoo@Bar") to its undecorated
+/// form (e.g., "Bar::CFoo")
+pub fn undecorate_qualified_name(qualified_name: &str) -> String {
+    qualified_name.rsplit('@').collect::<Vec<_>>().join("::")
+}
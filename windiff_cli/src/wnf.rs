@@ -0,0 +1,197 @@
+use std::collections::BTreeMap;
+
+use goblin::pe;
+use serde::Serialize;
+
+use crate::{
+    error::Result,
+    pe_utils::{
+        read_bytes, read_c_string, read_pointer_as_rva, read_u64, rva_to_offset, utf16_to_string,
+    },
+};
+
+/// Name of the table of well-known state names (in perf_nt_c.dll)
+const WELL_KNOWN_NAMES_TABLE_NAME: &str = "g_WellKnownWnfNames";
+/// Prefix of the state name symbols (in kernel binaries)
+const STATE_NAME_PREFIX: &str = "WNF_";
+/// Key state names are XORed with
+const STATE_NAME_XOR_KEY: u64 = 0x41c6_4e6d_a3bc_0074;
+/// Upper bound used to stop walking malformed tables
+const MAX_WELL_KNOWN_NAME_COUNT: usize = 0x10000;
+/// Upper bound of the descriptions' length, in characters
+const MAX_DESCRIPTION_LENGTH: usize = 0x1000;
+
+/// WNF state name, decoded
+#[derive(Serialize, Debug)]
+pub struct WnfStateName {
+    /// Encoded state name, as used by the WNF APIs
+    pub state_name: String,
+    pub version: u8,
+    pub lifetime: WnfStateNameLifetime,
+    pub data_scope: WnfDataScope,
+    pub permanent_data: bool,
+    pub sequence: u64,
+    pub description: Option<String>,
+}
+
+/// `WNF_STATE_NAME_LIFETIME`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WnfStateNameLifetime {
+    WellKnown,
+    Permanent,
+    Persistent,
+    Temporary,
+}
+
+/// `WNF_DATA_SCOPE`
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WnfDataScope {
+    System,
+    Session,
+    User,
+    Process,
+    Machine,
+    PhysicalMachine,
+    Unknown,
+}
+
+/// Extract the WNF state names defined by a given PE, from the table of
+/// well-known names and from `WNF_*` symbols, as (name, state name) pairs.
+pub fn extract_wnf_state_names(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    symbols: &BTreeMap<u32, String>,
+) -> Result<Vec<(String, WnfStateName)>> {
+    let mut result = vec![];
+    for (symbol_rva, symbol_name) in symbols {
+        // 32-bit binaries use decorated names
+        let symbol_name = symbol_name.trim_start_matches('_');
+        if symbol_name == WELL_KNOWN_NAMES_TABLE_NAME {
+            log::trace!("Extracting well-known WNF state names");
+            match extract_well_known_names(pe, pe_data, *symbol_rva) {
+                Ok(well_known_names) => result.extend(well_known_names),
+                Err(err) => log::debug!("Failed to read well-known WNF state names: {}", err),
+            }
+        } else if symbol_name.starts_with(STATE_NAME_PREFIX) {
+            let state_name_offset = match rva_to_offset(*symbol_rva as usize, pe) {
+                Ok(state_name_offset) => state_name_offset,
+                // Symbols might not be backed by file data
+                Err(_) => continue,
+            };
+            match read_u64(pe_data, state_name_offset) {
+                Ok(state_name) => {
+                    result.push((symbol_name.to_string(), decode_state_name(state_name, None)))
+                }
+                Err(err) => log::debug!("Failed to read '{}': {}", symbol_name, err),
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Walk a table of `{ WNF_STATE_NAME StateName; PCSTR Name; PCWSTR
+/// Description; }` entries, terminated by a null entry.
+fn extract_well_known_names(
+    pe: &pe::PE<'_>,
+    pe_data: &[u8],
+    table_rva: u32,
+) -> Result<Vec<(String, WnfStateName)>> {
+    let pointer_size = if pe.is_64 { 8 } else { 4 };
+    let entry_size = 8 + 2 * pointer_size;
+    let table_offset = rva_to_offset(table_rva as usize, pe)?;
+
+    let mut result = vec![];
+    for entry_index in 0..MAX_WELL_KNOWN_NAME_COUNT {
+        let entry_offset = table_offset + entry_index * entry_size;
+        let entry = read_u64(pe_data, entry_offset).and_then(|state_name| {
+            Ok((
+                state_name,
+                read_pointer_as_rva(pe, pe_data, entry_offset + 8)?,
+            ))
+        });
+        let (state_name, name_rva) = match entry {
+            Ok((0, _) | (_, None)) => break,
+            Ok((state_name, Some(name_rva))) => (state_name, name_rva),
+            Err(err) => {
+                log::debug!("Stopping at malformed WNF state name entry: {}", err);
+                break;
+            }
+        };
+
+        let name = match read_string(pe, pe_data, name_rva) {
+            Ok(name) => name,
+            Err(err) => {
+                log::debug!(
+                    "Failed to read the name of WNF state name {:#x}: {}",
+                    state_name,
+                    err
+                );
+                continue;
+            }
+        };
+        let description = read_pointer_as_rva(pe, pe_data, entry_offset + 8 + pointer_size)
+            .and_then(|description_rva| {
+                description_rva
+                    .map(|description_rva| read_string(pe, pe_data, description_rva))
+                    .transpose()
+            })
+            .unwrap_or_else(|err| {
+                log::debug!("Failed to read the description of '{}': {}", name, err);
+                None
+            });
+        result.push((name, decode_state_name(state_name, description)));
+    }
+
+    Ok(result)
+}
+
+/// Read a NUL-terminated string that might be either ASCII or UTF-16LE
+fn read_string(pe: &pe::PE<'_>, pe_data: &[u8], rva: u32) -> Result<String> {
+    let offset = rva_to_offset(rva as usize, pe)?;
+    if read_bytes(pe_data, offset, 2)?[1] != 0 {
+        return read_c_string(pe_data, offset);
+    }
+
+    let string_data = read_bytes(
+        pe_data,
+        offset,
+        (2 * MAX_DESCRIPTION_LENGTH).min(pe_data.len().saturating_sub(offset)),
+    )?;
+    let string_length = string_data
+        .chunks_exact(2)
+        .position(|c| c == [0, 0])
+        .unwrap_or(string_data.len() / 2);
+
+    Ok(utf16_to_string(&string_data[..2 * string_length]))
+}
+
+/// Decode the fields of a `WNF_STATE_NAME`
+fn decode_state_name(state_name: u64, description: Option<String>) -> WnfStateName {
+    let decoded_state_name = state_name ^ STATE_NAME_XOR_KEY;
+
+    WnfStateName {
+        state_name: format!("{:#018x}", state_name),
+        version: (decoded_state_name & 0xf) as u8,
+        lifetime: match (decoded_state_name >> 4) & 0x3 {
+            0 => WnfStateNameLifetime::WellKnown,
+            1 => WnfStateNameLifetime::Permanent,
+            2 => WnfStateNameLifetime::Persistent,
+            _ => WnfStateNameLifetime::Temporary,
+        },
+        data_scope: match (decoded_state_name >> 6) & 0xf {
+            0 => WnfDataScope::System,
+            1 => WnfDataScope::Session,
+            2 => WnfDataScope::User,
+            3 => WnfDataScope::Process,
+            4 => WnfDataScope::Machine,
+            5 => WnfDataScope::PhysicalMachine,
+            _ => WnfDataScope::Unknown,
+        },
+        permanent_data: (decoded_state_name >> 10) & 0x1 != 0,
+        sequence: decoded_state_name >> 11,
+        description,
+    }
+}
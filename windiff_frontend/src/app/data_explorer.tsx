@@ -48,6 +48,7 @@ export enum Tab {
   TypeList = 3,
   Types = 4,
   Sycalls = 5,
+  ApiSet = 6,
 }
 
 const indexFilePath: string = "/index.json.gz";
@@ -58,6 +59,7 @@ const tabNames: string[] = [
   "Types",
   "Reconstructed Types",
   "Syscalls",
+  "API Sets",
 ];
 
 // List of binaries we support syscall extraction for
@@ -67,9 +69,12 @@ const supportedBinariesForSyscalls: string[] = [
   "ntoskrnl.exe",
   "win32k.sys",
 ];
+// List of binaries we support API set schema extraction for
+const supportedBinariesForApiSet: string[] = ["apisetschema.dll"];
 
-// Filters the binary list depending on the active tab. The Syscalls tab keeps
-// only binaries we extract syscalls from; the Debug Symbols, Modules and
+// Filters the binary list depending on the active tab. The Syscalls and API
+// Sets tabs keep only binaries we extract syscalls or API sets from; the Debug
+// Symbols, Modules and
 // (Reconstructed) Types tabs keep only binaries that have the corresponding
 // PDB-derived data for the selected OS version(s) (the left version in browse
 // mode, the union of both versions in diff mode). These presence maps are
@@ -87,6 +92,11 @@ function filterBinariesForTab(
       (binary) => supportedBinariesForSyscalls.indexOf(binary) > -1
     );
   }
+  if (tab == Tab.ApiSet) {
+    return binaries.filter(
+      (binary) => supportedBinariesForApiSet.indexOf(binary) > -1
+    );
+  }
 
   // Pick the per-OS-version presence map matching the active tab, if any
   let presenceMap: { [osPathSuffix: string]: string[] } | undefined;
@@ -607,6 +617,19 @@ function getEditorDataFromFileData(
           }
         })
         .join("\n");
+    case Tab.ApiSet:
+      // One line per contract, followed by its importer-specific hosts
+      return Object.entries(fileData.api_set ?? {})
+        .map(([contractName, contract]) =>
+          [`${contractName} -> ${contract.hosts.join(", ")}`]
+            .concat(
+              Object.entries(contract.exceptions).map(
+                ([importerName, hostName]) => `    ${importerName} -> ${hostName}`
+              )
+            )
+            .join("\n")
+        )
+        .join("\n");
   }
 }
 
@@ -19,6 +19,7 @@ export const TAB_KEYS = [
   "typelist", // Tab.TypeList = 3
   "types",    // Tab.Types = 4
   "syscalls", // Tab.Sycalls = 5
+  "apiset",   // Tab.ApiSet = 6
 ] as const;
 
 export function readParam(key: string): string | null {
@@ -10,6 +10,11 @@ export type WinDiffIndexData = {
   binaries_with_symbols?: { [osPathSuffix: string]: WinDiffIndexBinary[] };
   binaries_with_modules?: { [osPathSuffix: string]: WinDiffIndexBinary[] };
   binaries_with_types?: { [osPathSuffix: string]: WinDiffIndexBinary[] };
+  // Subset of `binaries_with_symbols` whose symbols were synthesized from the
+  // binary's exported functions because no PDB was available.
+  binaries_with_synthesized_symbols?: {
+    [osPathSuffix: string]: WinDiffIndexBinary[];
+  };
 };
 export type WinDiffIndexOS = {
   version: string;
@@ -26,4 +31,11 @@ export type WinDiffFileData = {
   modules: string[];
   types: { [typeName: string]: string };
   syscalls: { [syscallId: string]: string };
+  // Only present in databases generated with the APISET extraction kind
+  api_set?: { [contractName: string]: WinDiffApiSetContract };
+};
+export type WinDiffApiSetContract = {
+  hosts: string[];
+  // Importing DLL -> host DLL
+  exceptions: { [importerName: string]: string };
 };